@@ -0,0 +1,250 @@
+//! An agent, holding tokens in memory and serving them through a Unix socket
+
+use crate::{config::ClientState, http::HttpOptions, tokens::TokenSource};
+use anyhow::{Context, bail};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use time::OffsetDateTime;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{Mutex, OnceCell},
+};
+use url::Url;
+
+/// The variable advertising the socket of the agent
+pub const AGENT_SOCK: &str = "OIDC_AGENT_SOCK";
+
+/// Maximum value of a requested minimum validity
+const MAX_MIN_VALID: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The socket of a running agent, if advertised
+pub fn socket() -> Option<PathBuf> {
+    std::env::var_os(AGENT_SOCK)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// A request for a token
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Request {
+    /// Name of the client
+    pub client: String,
+    /// Force a new token
+    #[serde(default)]
+    pub force: bool,
+    /// Minimum remaining validity of the token, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_valid: Option<u64>,
+    /// Create a DPoP proof for a request, using the access token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<ProofRequest>,
+    /// Provide the thumbprint of the client certificate
+    #[serde(default)]
+    pub thumbprint: bool,
+}
+
+/// The request a DPoP proof is created for
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ProofRequest {
+    pub method: String,
+    pub url: Url,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+}
+
+/// A token, and the details of the client it is bound to
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Token {
+    pub state: ClientState,
+    /// If the client uses DPoP
+    #[serde(default)]
+    pub dpop: bool,
+    /// The requested DPoP proof
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<String>,
+    /// The thumbprint of the client certificate (`x5t#S256`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbprint: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Response {
+    Token(Token),
+    Error(String),
+}
+
+/// Connect to the agent
+pub async fn connect(socket: &Path) -> anyhow::Result<UnixStream> {
+    UnixStream::connect(socket)
+        .await
+        .with_context(|| format!("unable to connect to agent: {}", socket.display()))
+}
+
+/// Request a token from the agent
+pub async fn request(stream: UnixStream, request: &Request) -> anyhow::Result<Token> {
+    let (read, mut write) = stream.into_split();
+
+    let mut data = serde_json::to_vec(request)?;
+    data.push(b'\n');
+    write.write_all(&data).await?;
+
+    let Some(line) = BufReader::new(read).lines().next_line().await? else {
+        bail!("agent closed the connection");
+    };
+
+    match serde_json::from_str(&line)? {
+        Response::Token(token) => Ok(token),
+        Response::Error(err) => bail!("agent failed to provide token: {err}"),
+    }
+}
+
+/// Holds the clients, loading them on their first request
+pub struct Agent {
+    config: Option<PathBuf>,
+    http: HttpOptions,
+    min_valid: Option<Duration>,
+    clients: Mutex<HashMap<String, Arc<OnceCell<Arc<TokenSource>>>>>,
+}
+
+impl Agent {
+    pub fn new(config: Option<PathBuf>, http: HttpOptions, min_valid: Option<Duration>) -> Self {
+        Self {
+            config,
+            http,
+            min_valid,
+            clients: Default::default(),
+        }
+    }
+
+    /// Serve requests, until the task gets cancelled
+    pub async fn serve(self: Arc<Self>, listener: UnixListener) -> anyhow::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let agent = self.clone();
+            tokio::spawn(async move {
+                if let Err(err) = agent.handle(stream).await {
+                    log::warn!("Failed to handle request: {err}");
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, stream: UnixStream) -> anyhow::Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+
+        while let Some(line) = lines.next_line().await? {
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => match self.token(&request).await {
+                    Ok(token) => Response::Token(token),
+                    Err(err) => Response::Error(format!("{err:#}")),
+                },
+                Err(err) => Response::Error(format!("invalid request: {err}")),
+            };
+
+            let mut data = serde_json::to_vec(&response)?;
+            data.push(b'\n');
+            write.write_all(&data).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn token(&self, request: &Request) -> anyhow::Result<Token> {
+        log::debug!("Request: {request:?}");
+
+        let min_valid = request.min_valid.map(Duration::from_secs);
+        if min_valid.is_some_and(|min_valid| min_valid > MAX_MIN_VALID) {
+            bail!(
+                "minimum validity must not exceed {}",
+                humantime::format_duration(MAX_MIN_VALID)
+            );
+        }
+
+        let tokens = self.client(&request.client).await?;
+        let mut state = tokens.state().await;
+
+        // the background refresh may have failed, or been delayed (e.g. by a suspend)
+        let min_valid = min_valid.unwrap_or(tokens.min_valid());
+        let expiring = match state.expiration() {
+            Some(expires) => time::Duration::try_from(min_valid)
+                .ok()
+                .and_then(|min_valid| expires.checked_sub(min_valid))
+                // beyond the representable range, the token is considered expiring
+                .is_none_or(|refresh| refresh <= OffsetDateTime::now_utc()),
+            // without an expiration, the token will only be renewed when it is rejected
+            None => false,
+        };
+
+        if request.force || expiring {
+            tokens.renew(&state.access_token).await?;
+            state = tokens.state().await;
+        }
+
+        let proof = match (&request.proof, tokens.dpop()) {
+            (Some(proof), Some(dpop)) => Some(dpop.proof(
+                &proof.method,
+                &proof.url,
+                proof.nonce.as_deref(),
+                Some(&state.access_token),
+            )?),
+            _ => None,
+        };
+
+        let thumbprint = match request.thumbprint {
+            true => tokens
+                .http()
+                .certificate()
+                .map(|certificate| certificate.thumbprint())
+                .transpose()?,
+            false => None,
+        };
+
+        Ok(Token {
+            dpop: tokens.dpop().is_some(),
+            state,
+            proof,
+            thumbprint,
+        })
+    }
+
+    /// Get a loaded client, or load it, keeping its token valid in the background
+    async fn client(&self, name: &str) -> anyhow::Result<Arc<TokenSource>> {
+        // only hold the lock for looking up the client, loading may take a while
+        let cell = self
+            .clients
+            .lock()
+            .await
+            .entry(name.to_string())
+            .or_default()
+            .clone();
+
+        let tokens = cell
+            .get_or_try_init(async || {
+                log::info!("Loading client: {name}");
+                let tokens = Arc::new(
+                    TokenSource::new(
+                        self.config.clone(),
+                        name.to_string(),
+                        self.http.clone(),
+                        self.min_valid,
+                    )
+                    .await?,
+                );
+
+                let refresh = tokens.clone();
+                tokio::spawn(async move { refresh.keep_valid().await });
+
+                Ok::<_, anyhow::Error>(tokens)
+            })
+            .await?;
+
+        Ok(tokens.clone())
+    }
+}
@@ -0,0 +1,308 @@
+//! An on-disk cache of provider metadata and key sets
+//!
+//! Entries are keyed by their URL, and honor the `Cache-Control` and `ETag` headers of the
+//! response. Entries are kept no longer than the configured TTL, and stale entries are used when
+//! the provider cannot be reached.
+
+use anyhow::{Context, bail};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use reqwest::{StatusCode, header};
+use serde_json::Value;
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+use time::{OffsetDateTime, PrimitiveDateTime};
+use url::Url;
+
+/// Maximum lifetime of an entry, unless configured otherwise
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+static TTL: OnceLock<Duration> = OnceLock::new();
+
+/// Set the maximum lifetime of an entry, a zero duration revalidates entries on every use
+pub fn init(ttl: Duration) {
+    let _ = TTL.set(ttl);
+}
+
+fn ttl() -> Duration {
+    TTL.get().copied().unwrap_or(DEFAULT_TTL)
+}
+
+/// The directory of the cache
+pub fn dir() -> Option<PathBuf> {
+    let base = directories::ProjectDirs::from("de.dentrassi", "ctron", "oidc")?;
+    Some(base.cache_dir().join("http"))
+}
+
+/// A cached response
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    pub url: Url,
+    #[serde(with = "time::serde::rfc3339")]
+    pub fetched: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires: OffsetDateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    pub body: Value,
+}
+
+impl Entry {
+    /// Check if the entry can be used without revalidating it, also applying the current TTL
+    pub fn is_fresh(&self) -> bool {
+        self.expires.min(saturating_add(self.fetched, ttl())) > OffsetDateTime::now_utc()
+    }
+}
+
+/// All entries of the cache
+pub fn entries() -> anyhow::Result<Vec<Entry>> {
+    let Some(dir) = dir() else {
+        return Ok(vec![]);
+    };
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut entries = vec![];
+    for file in std::fs::read_dir(&dir)? {
+        let path = file?.path();
+        if path.extension().is_some_and(|ext| ext == "json")
+            && let Some(entry) = read(&path)
+        {
+            entries.push(entry);
+        }
+    }
+
+    entries.sort_by(|a, b| a.url.cmp(&b.url));
+
+    Ok(entries)
+}
+
+/// Remove all entries, returning the number of removed entries
+pub fn clear() -> anyhow::Result<usize> {
+    let Some(dir) = dir() else {
+        return Ok(0);
+    };
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let mut removed = 0;
+    for file in std::fs::read_dir(&dir)? {
+        let path = file?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("unable to remove: {}", path.display()))?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Fetch a JSON document, using the cache when possible
+pub async fn fetch(http: &reqwest::Client, url: &Url) -> anyhow::Result<Vec<u8>> {
+    load(http, url, false).await
+}
+
+/// Fetch a JSON document, revalidating a cached entry even if it is still fresh
+pub async fn revalidate(http: &reqwest::Client, url: &Url) -> anyhow::Result<Vec<u8>> {
+    load(http, url, true).await
+}
+
+async fn load(http: &reqwest::Client, url: &Url, revalidate: bool) -> anyhow::Result<Vec<u8>> {
+    let Some(path) = dir().map(|dir| dir.join(file_name(url))) else {
+        // without a cache directory, always fetch the document
+        let response = request(http, url, None).await?;
+        return Ok(response.body.unwrap_or_default());
+    };
+
+    let cached = read(&path).filter(|entry| &entry.url == url);
+    if let Some(entry) = &cached
+        && !revalidate
+        && entry.is_fresh()
+    {
+        log::debug!("Using cached response: {url}");
+        return Ok(serde_json::to_vec(&entry.body)?);
+    }
+
+    let etag = cached.as_ref().and_then(|entry| entry.etag.as_deref());
+    let response = match request(http, url, etag).await {
+        Ok(response) => response,
+        Err(err) => match cached {
+            Some(entry) => {
+                log::warn!("Failed to fetch {url}, using stale cached response: {err}");
+                return Ok(serde_json::to_vec(&entry.body)?);
+            }
+            None => return Err(err),
+        },
+    };
+
+    let (body, etag) = match (response.body, cached) {
+        (Some(body), _) => (body, response.etag),
+        (None, Some(entry)) => {
+            log::debug!("Cached response is still valid: {url}");
+            // the server may announce a new entity tag with the response
+            (
+                serde_json::to_vec(&entry.body)?,
+                response.etag.or(entry.etag),
+            )
+        }
+        (None, None) => bail!("unexpected response, not modified: {url}"),
+    };
+
+    let ttl = match response.lifetime {
+        Lifetime::Default => Some(ttl()),
+        Lifetime::MaxAge(max_age) => Some(max_age.min(ttl())),
+        Lifetime::NoStore => None,
+    };
+
+    match ttl {
+        None => {
+            let _ = std::fs::remove_file(&path);
+        }
+        Some(ttl) => {
+            let value: Value = serde_json::from_slice(&body)
+                .with_context(|| format!("parsing response: {url}"))?;
+
+            let fetched = OffsetDateTime::now_utc();
+            let entry = Entry {
+                url: url.clone(),
+                fetched,
+                expires: saturating_add(fetched, ttl),
+                etag,
+                body: value,
+            };
+
+            if let Err(err) = write(&path, &entry) {
+                log::warn!("Failed to store cached response: {err}");
+            }
+        }
+    }
+
+    Ok(body)
+}
+
+struct Response {
+    /// The body, `None` if the response was not modified
+    body: Option<Vec<u8>>,
+    etag: Option<String>,
+    lifetime: Lifetime,
+}
+
+/// The lifetime of a response, as announced by the server
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Lifetime {
+    /// Nothing announced, use the configured TTL
+    Default,
+    /// Cache for the duration, but no longer than the configured TTL
+    MaxAge(Duration),
+    /// Must not be stored
+    NoStore,
+}
+
+/// Perform the request, a response which was not modified has no body
+async fn request(
+    http: &reqwest::Client,
+    url: &Url,
+    etag: Option<&str>,
+) -> anyhow::Result<Response> {
+    let mut request = http
+        .get(url.clone())
+        .header(header::ACCEPT, "application/json");
+    if let Some(etag) = etag {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+
+    let response = request.send().await?;
+    let modified = !(response.status() == StatusCode::NOT_MODIFIED && etag.is_some());
+    let response = response
+        .error_for_status()
+        .with_context(|| format!("fetching: {url}"))?;
+
+    // a "not modified" response carries the headers it would have had otherwise
+    let etag = response
+        .headers()
+        .get(header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string);
+    let lifetime = lifetime(response.headers());
+
+    let body = match modified {
+        true => Some(response.bytes().await?.to_vec()),
+        false => None,
+    };
+
+    Ok(Response {
+        body,
+        etag,
+        lifetime,
+    })
+}
+
+/// Evaluate the lifetime of a response from its `Cache-Control` header
+fn lifetime(headers: &header::HeaderMap) -> Lifetime {
+    let mut result = Lifetime::Default;
+
+    let directives = headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim);
+
+    for directive in directives {
+        match directive.split_once('=') {
+            None if directive == "no-store" => return Lifetime::NoStore,
+            None if directive == "no-cache" => result = Lifetime::MaxAge(Duration::ZERO),
+            Some(("max-age", value)) if result == Lifetime::Default => {
+                if let Ok(seconds) = value.trim_matches('"').parse() {
+                    result = Lifetime::MaxAge(Duration::from_secs(seconds));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    result
+}
+
+/// Add a duration, saturating at the latest representable point in time
+fn saturating_add(time: OffsetDateTime, duration: Duration) -> OffsetDateTime {
+    time::Duration::try_from(duration)
+        .ok()
+        .and_then(|duration| time.checked_add(duration))
+        .unwrap_or(PrimitiveDateTime::MAX.assume_utc())
+}
+
+/// The name of the file of an entry, which is the SHA-256 digest of its URL
+fn file_name(url: &Url) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, url.as_str().as_bytes());
+    format!("{}.json", URL_SAFE_NO_PAD.encode(digest))
+}
+
+fn read(path: &Path) -> Option<Entry> {
+    let data = std::fs::read(path).ok()?;
+    match serde_json::from_slice(&data) {
+        Ok(entry) => Some(entry),
+        Err(err) => {
+            log::debug!("Ignoring invalid cache entry ({}): {err}", path.display());
+            None
+        }
+    }
+}
+
+fn write(path: &Path, entry: &Entry) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // write to a temporary file first, so that concurrent readers never see a partial entry
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    std::fs::write(&temp, serde_json::to_vec_pretty(entry)?)?;
+    std::fs::rename(&temp, path)?;
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
-use biscuit::{CompactJson, SingleOrMultiple};
+use biscuit::{CompactJson, CompactPart, SingleOrMultiple};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use time::OffsetDateTime;
 use url::Url;
 
 /// Access token claims
@@ -40,3 +41,64 @@ pub struct RefreshTokenClaims {
 }
 
 impl CompactJson for RefreshTokenClaims {}
+
+/// Decode a token and extract its expiration
+///
+/// NOTE: The token is not being verified.
+pub fn expiration(token: &str) -> Option<OffsetDateTime> {
+    // only requires the `exp` claim, which works for all kinds of tokens
+    let token = biscuit::jws::Compact::<RefreshTokenClaims, biscuit::Empty>::new_encoded(token)
+        .unverified_payload()
+        .ok()?;
+
+    token
+        .exp
+        .and_then(|exp| OffsetDateTime::from_unix_timestamp(exp).ok())
+}
+
+/// Decode the claims of a token, without verifying it
+pub fn decode(token: &str) -> anyhow::Result<Value> {
+    let token = biscuit::Compact::decode(token);
+    let payload = token
+        .parts
+        .get(1)
+        .ok_or_else(|| anyhow::anyhow!("token has no payload"))?;
+
+    Ok(serde_json::from_slice(&payload.to_bytes()?)?)
+}
+
+/// Select a claim, using either a JSON pointer (`/realm_access/roles`) or a dotted path
+/// (`realm_access.roles.0`)
+pub fn select<'a>(claims: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.starts_with('/') {
+        return claims.pointer(path);
+    }
+
+    path.split('.')
+        .try_fold(claims, |value, segment| match value {
+            Value::Array(values) => values.get(segment.parse::<usize>().ok()?),
+            value => value.get(segment),
+        })
+}
+
+/// Print the selected claims of a token, one per line, failing if a claim is absent
+///
+/// Values are printed as JSON, unless `raw` is set, in which case strings are printed unquoted.
+pub fn print_claims(token: &str, paths: &[String], raw: bool) -> anyhow::Result<()> {
+    let claims = decode(token)?;
+
+    let mut missing = vec![];
+    for path in paths {
+        match select(&claims, path) {
+            None => missing.push(path.as_str()),
+            Some(Value::String(value)) if raw => println!("{value}"),
+            Some(value) => println!("{}", serde_json::to_string(value)?),
+        }
+    }
+
+    if !missing.is_empty() {
+        anyhow::bail!("claim not found: {}", missing.join(", "));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use crate::{
+    agent::{AGENT_SOCK, Agent},
+    http::HttpOptions,
+};
+use anyhow::{Context, bail};
+use std::{
+    fs::{DirBuilder, Permissions},
+    io::Write,
+    os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    net::{UnixListener, UnixStream},
+    signal::unix::{SignalKind, signal},
+};
+
+/// Run an agent, keeping tokens in memory and serving them through a Unix socket
+///
+/// The agent prints the shell commands for advertising its socket, using the
+/// `OIDC_AGENT_SOCK` variable. Commands like `oidc token` will then use the agent.
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct AgentCommand {
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// The path of the socket, defaults to a private location. Its directory must be owned by the
+    /// current user, and must not be writable by other users.
+    #[arg(short, long)]
+    pub socket: Option<PathBuf>,
+
+    /// Refresh tokens when they are valid for less than this duration, overrides the default of
+    /// the clients [default: 30s]
+    #[arg(long)]
+    pub min_valid: Option<humantime::Duration>,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+impl AgentCommand {
+    pub async fn run(self) -> anyhow::Result<()> {
+        let socket = match &self.socket {
+            Some(socket) => socket.clone(),
+            None => default_socket()?,
+        };
+
+        if socket.exists() {
+            if UnixStream::connect(&socket).await.is_ok() {
+                bail!("an agent is already running: {}", socket.display());
+            }
+            log::info!("Removing stale socket: {}", socket.display());
+            std::fs::remove_file(&socket)?;
+        }
+
+        let listener = bind(&socket)
+            .with_context(|| format!("unable to bind socket: {}", socket.display()))?;
+
+        println!("{AGENT_SOCK}={}; export {AGENT_SOCK};", socket.display());
+        std::io::stdout().flush()?;
+
+        let agent = Arc::new(Agent::new(
+            self.config.clone(),
+            self.http.clone(),
+            self.min_valid.map(Into::into),
+        ));
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        let result = tokio::select! {
+            result = agent.serve(listener) => result,
+            _ = tokio::signal::ctrl_c() => Ok(()),
+            _ = terminate.recv() => Ok(()),
+        };
+
+        log::info!("Shutting down agent");
+        let _ = std::fs::remove_file(&socket);
+        if self.socket.is_none()
+            && let Some(dir) = socket.parent()
+        {
+            // only succeeds if the directory is empty
+            let _ = std::fs::remove_dir(dir);
+        }
+
+        result
+    }
+}
+
+/// Bind the socket, only accessible by the current user.
+///
+/// The socket is bound in a private directory first, and moved to its location once its
+/// permissions are set. So other users can't connect in the meantime.
+fn bind(socket: &Path) -> anyhow::Result<UnixListener> {
+    let parent = socket
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    let mode = check_owner(parent)?;
+    if mode & 0o022 != 0 {
+        bail!("directory is writable by other users: {}", parent.display());
+    }
+
+    let private = parent.join(format!(".oidc-agent-{}", std::process::id()));
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&private)
+        .with_context(|| format!("unable to create directory: {}", private.display()))?;
+
+    let temp = private.join("agent.sock");
+    let result = (|| {
+        let listener = UnixListener::bind(&temp)?;
+        std::fs::set_permissions(&temp, Permissions::from_mode(0o600))?;
+        std::fs::rename(&temp, socket)?;
+        Ok(listener)
+    })();
+
+    let _ = std::fs::remove_file(&temp);
+    let _ = std::fs::remove_dir(&private);
+
+    result
+}
+
+/// A socket in the runtime directory of the user, or a private directory
+fn default_socket() -> anyhow::Result<PathBuf> {
+    let runtime_dir = directories::ProjectDirs::from("de.dentrassi", "ctron", "oidc")
+        .and_then(|dirs| dirs.runtime_dir().map(ToOwned::to_owned));
+
+    let dir = match runtime_dir {
+        Some(dir) => {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(&dir)
+                .with_context(|| format!("unable to create directory: {}", dir.display()))?;
+            dir
+        }
+        None => {
+            // the name is predictable, so it must not exist already, as another user might have
+            // created it
+            let dir = std::env::temp_dir().join(format!("oidc-agent-{}", std::process::id()));
+            DirBuilder::new()
+                .mode(0o700)
+                .create(&dir)
+                .with_context(|| format!("unable to create directory: {}", dir.display()))?;
+            dir
+        }
+    };
+
+    // an existing directory keeps its permissions
+    let mode = check_owner(&dir)?;
+    if mode & 0o777 != 0o700 {
+        bail!("directory is accessible by other users: {}", dir.display());
+    }
+
+    Ok(dir.join("agent.sock"))
+}
+
+/// Check that the directory is owned by the current user, returning its mode
+fn check_owner(dir: &Path) -> anyhow::Result<u32> {
+    let metadata = std::fs::metadata(dir)
+        .with_context(|| format!("unable to access directory: {}", dir.display()))?;
+
+    // SAFETY: geteuid has no preconditions and always succeeds
+    let uid = unsafe { libc::geteuid() };
+    if metadata.uid() != uid {
+        bail!(
+            "directory is not owned by the current user: {}",
+            dir.display()
+        );
+    }
+
+    Ok(metadata.permissions().mode())
+}
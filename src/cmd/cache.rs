@@ -0,0 +1,81 @@
+use crate::cache;
+use comfy_table::{Cell, Color, ContentArrangement, Table, presets};
+use time::{OffsetDateTime, macros::format_description};
+
+/// Manage the cache of provider metadata and key sets
+#[derive(Debug, clap::Parser)]
+pub struct Cache {
+    #[command(subcommand)]
+    pub command: CacheCommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum CacheCommand {
+    /// Show the cached entries
+    Show(Show),
+    /// Remove all cached entries
+    Clear,
+}
+
+impl Cache {
+    pub async fn run(self) -> anyhow::Result<()> {
+        match self.command {
+            CacheCommand::Show(cmd) => cmd.run(),
+            CacheCommand::Clear => {
+                let removed = cache::clear()?;
+                log::info!("Removed {removed} cached entries");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct Show {
+    /// Show the cached content of the entries
+    #[arg(short, long)]
+    pub details: bool,
+}
+
+impl Show {
+    fn run(self) -> anyhow::Result<()> {
+        if let Some(dir) = cache::dir() {
+            log::info!("Cache directory: {}", dir.display());
+        }
+
+        let entries = cache::entries()?;
+
+        if self.details {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+            return Ok(());
+        }
+
+        let mut table = Table::new();
+        table
+            .load_preset(presets::ASCII_MARKDOWN)
+            .set_content_arrangement(ContentArrangement::Dynamic);
+        table.set_header(["URL", "Fetched", "Expires", "ETag"]);
+
+        for entry in &entries {
+            table.add_row([
+                Cell::new(entry.url.as_str()),
+                Cell::new(format(entry.fetched)?),
+                match entry.is_fresh() {
+                    true => Cell::new(format(entry.expires)?).fg(Color::Green),
+                    false => Cell::new(format(entry.expires)?).fg(Color::DarkGrey),
+                },
+                Cell::new(entry.etag.as_deref().unwrap_or_default()),
+            ]);
+        }
+
+        println!("{table}");
+
+        Ok(())
+    }
+}
+
+fn format(value: OffsetDateTime) -> anyhow::Result<String> {
+    Ok(value.format(format_description!(
+        "[year]-[month]-[day] [hour]:[minute]:[second]Z"
+    ))?)
+}
@@ -1,7 +1,8 @@
 use crate::{
-    cmd::create::CreateCommon,
-    config::{Client, ClientType, Config},
-    http::HttpOptions,
+    cmd::create::{CreateCommon, login::CodeFlow},
+    config::{Client, ClientAuthentication, ClientType, Config},
+    http::{ClientCertificate, HttpOptions},
+    jose::{SigningAlgorithm, SigningKey},
     oidc::{TokenResult, get_token},
     utils::OrNone,
 };
@@ -23,16 +24,100 @@ pub struct CreateConfidential {
 
     /// The client secret
     #[arg(short = 's', long)]
-    pub client_secret: String,
+    pub client_secret: Option<String>,
+
+    /// How to authenticate with the token endpoint
+    #[arg(long, value_enum, default_value_t = AuthMethod::ClientSecretBasic)]
+    pub auth_method: AuthMethod,
+
+    /// The private key (PEM or JWK) for signing client assertions, implies 'private-key-jwt'
+    #[arg(long)]
+    pub private_key: Option<PathBuf>,
+
+    /// The key ID to use when signing client assertions
+    #[arg(long, requires = "private_key")]
+    pub key_id: Option<String>,
+
+    /// The algorithm to use when signing client assertions
+    #[arg(long, value_enum)]
+    pub signing_algorithm: Option<SigningAlgorithm>,
+
+    /// Log in a user interactively, using the authorization code flow, instead of using the
+    /// client credentials grant
+    #[arg(long)]
+    pub login: bool,
+
+    #[command(flatten)]
+    pub code_flow: CodeFlow,
 
     #[command(flatten)]
     pub http: HttpOptions,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum AuthMethod {
+    /// Send the client secret
+    ClientSecretBasic,
+    /// Send a JWT, signed with the client secret
+    ClientSecretJwt,
+    /// Send a JWT, signed with a private key
+    PrivateKeyJwt,
+    /// Use mutual TLS with the client certificate
+    TlsClientAuth,
+}
+
 impl CreateConfidential {
+    fn authentication(&self) -> anyhow::Result<ClientAuthentication> {
+        let method = match self.private_key {
+            Some(_) => AuthMethod::PrivateKeyJwt,
+            None => self.auth_method,
+        };
+
+        Ok(match method {
+            AuthMethod::ClientSecretBasic | AuthMethod::TlsClientAuth
+                if self.signing_algorithm.is_some() =>
+            {
+                bail!("A signing algorithm requires the use of client assertions");
+            }
+            AuthMethod::ClientSecretBasic | AuthMethod::ClientSecretJwt
+                if self.client_secret.is_none() =>
+            {
+                bail!("Using '{method:?}' requires a client secret");
+            }
+            AuthMethod::ClientSecretBasic => ClientAuthentication::ClientSecret,
+            AuthMethod::ClientSecretJwt => ClientAuthentication::ClientSecretJwt {
+                algorithm: self.signing_algorithm,
+            },
+            AuthMethod::TlsClientAuth => {
+                let Some(certificate) = self.http.certificate() else {
+                    bail!("Using 'tls-client-auth' requires a client certificate");
+                };
+                ClientAuthentication::TlsClientAuth(ClientCertificate {
+                    certificate: std::path::absolute(&certificate.certificate)?,
+                    key: certificate.key.map(std::path::absolute).transpose()?,
+                    password: certificate.password,
+                })
+            }
+            AuthMethod::PrivateKeyJwt => {
+                let Some(key) = &self.private_key else {
+                    bail!("Using 'private-key-jwt' requires a private key");
+                };
+                // fail early, if we can't use the key
+                SigningKey::load(key, self.signing_algorithm)?;
+                ClientAuthentication::PrivateKeyJwt {
+                    key: std::path::absolute(key)?,
+                    key_id: self.key_id.clone(),
+                    algorithm: self.signing_algorithm,
+                }
+            }
+        })
+    }
+
     pub async fn run(self) -> anyhow::Result<()> {
         log::debug!("creating new client: {}", self.common.name);
 
+        let authentication = self.authentication()?;
+
         Config::locked(self.config.as_deref(), async |config| {
             if !self.common.force && config.clients.contains_key(&self.common.name) {
                 bail!(
@@ -47,17 +132,28 @@ impl CreateConfidential {
                 r#type: ClientType::Confidential {
                     client_id: self.client_id.clone(),
                     client_secret: self.client_secret.clone(),
+                    authentication: authentication.clone(),
+                    interactive: self.login,
                 },
                 state: None,
+                dpop: self.common.dpop_key()?,
+                registration: None,
+                min_valid: self.common.min_valid.map(Into::into),
             };
 
             if !self.common.skip_initial {
-                let token = get_token(&client, &self.http)
-                    .await
-                    .context("failed retrieving first token")?;
-
-                let token = match token {
-                    TokenResult::Refreshed(token) | TokenResult::Existing(token) => token,
+                let token = match self.login {
+                    true => self
+                        .code_flow
+                        .login(&self.common, &client.r#type, &self.http, client.dpop.clone())
+                        .await?
+                        .into(),
+                    false => match get_token(&client, &self.http)
+                        .await
+                        .context("failed retrieving first token")?
+                    {
+                        TokenResult::Refreshed(token) | TokenResult::Existing(token) => token,
+                    },
                 };
 
                 log::info!("First token:");
@@ -0,0 +1,206 @@
+use crate::{
+    cmd::create::CreateCommon,
+    config::{Client, ClientState, ClientType, Config},
+    dpop::{DpopClient, DpopKey},
+    http::{HttpOptions, create_client},
+    oidc::{
+        ExtendedProviderMetadata, discover, extra_scopes, other_audiences, rediscover, unknown_key,
+    },
+    utils::OrNone,
+};
+use anyhow::{Context, anyhow, bail};
+use oauth2::{ClientId, ClientSecret, EndpointMaybeSet, EndpointNotSet, EndpointSet};
+use openidconnect::{
+    Nonce,
+    core::{CoreClient, CoreDeviceAuthorizationResponse, CoreIdTokenVerifier},
+};
+use std::path::PathBuf;
+
+/// Create a new public client, using the device authorization grant
+#[derive(Debug, clap::Parser)]
+pub struct CreateDevice {
+    #[command(flatten)]
+    pub common: CreateCommon,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// The client ID
+    #[arg(short = 'i', long)]
+    pub client_id: String,
+
+    /// The client secret
+    #[arg(short = 's', long)]
+    pub client_secret: Option<String>,
+
+    /// Open the verification link automatically
+    #[arg(short, long)]
+    pub open: bool,
+
+    /// Maximum time to wait for the login, defaults to the lifetime of the device code
+    #[arg(long)]
+    pub login_timeout: Option<humantime::Duration>,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+type FlowClient = CoreClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+impl CreateDevice {
+    pub async fn run(self) -> anyhow::Result<()> {
+        log::debug!("creating new client: {}", self.common.name);
+
+        Config::locked(self.config.as_deref(), async |config| {
+            if !self.common.force && config.clients.contains_key(&self.common.name) {
+                bail!(
+                    "A client named '{}' already exists. You need to delete it first or use --force",
+                    self.common.name
+                );
+            }
+
+            let dpop = self.common.dpop_key()?;
+            let token = self.device_flow(dpop.clone()).await?;
+
+            log::info!("First token:");
+            log::info!("       ID: {}", OrNone(&token.id_token));
+            log::info!("   Access: {}", token.access_token);
+            log::info!("  Refresh: {}", OrNone(&token.refresh_token));
+
+            let client = Client {
+                issuer_url: self.common.issuer.clone(),
+                scope: self.common.scope.clone(),
+                r#type: ClientType::Public {
+                    client_id: self.client_id.clone(),
+                    client_secret: self.client_secret.clone(),
+                },
+                state: Some(token),
+                dpop,
+                registration: None,
+                min_valid: self.common.min_valid.map(Into::into),
+            };
+
+            config
+                .clients
+                .insert(self.common.name.clone(), client.clone());
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn device_flow(&self, dpop: Option<DpopKey>) -> anyhow::Result<ClientState> {
+        let http = create_client(&self.http).await?;
+
+        let provider_metadata = discover(
+            &self.common.issuer,
+            &http,
+            self.http.client_certificate.is_some(),
+        )
+        .await?;
+
+        let device_authorization_endpoint = provider_metadata
+            .additional_metadata()
+            .device_authorization_endpoint
+            .clone()
+            .ok_or_else(|| anyhow!("issuer does not support the device authorization grant"))?;
+
+        let client = self
+            .client(provider_metadata)
+            .set_device_authorization_url(device_authorization_endpoint);
+
+        let details: CoreDeviceAuthorizationResponse = client
+            .exchange_device_code()
+            .add_scopes(extra_scopes(self.common.scope.as_deref()))
+            .request_async(&http)
+            .await
+            .context("failed to request device code")?;
+
+        let open = details
+            .verification_uri_complete()
+            .map(|uri| uri.secret().to_string())
+            .unwrap_or_else(|| details.verification_uri().to_string());
+
+        println!(
+            r#"
+
+Open the following URL in your browser and perform the interactive login process (use --open to do this automatically):
+
+    {uri}
+
+When being asked, enter the following code:
+
+    {code}
+
+"#,
+            uri = details.verification_uri(),
+            code = details.user_code().secret(),
+        );
+
+        if self.open
+            && let Err(err) = open::that(&open)
+        {
+            log::warn!(
+                "Failed to open URL in browser. You can still copy the link from the console. Error: {err}"
+            );
+        }
+
+        let token = client
+            .exchange_device_access_token(&details)?
+            .request_async(
+                &DpopClient::new(http.clone(), dpop),
+                tokio::time::sleep,
+                self.login_timeout.map(Into::into),
+            )
+            .await
+            .context("failed to retrieve token")?;
+
+        if let Some(id_token) = token.extra_fields().id_token() {
+            // the device flow doesn't use a nonce
+            let nonce = |_: Option<&Nonce>| Ok(());
+
+            let verifier = self.id_token_verifier(client.id_token_verifier());
+            let mut result = id_token.clone().into_claims(&verifier, nonce);
+
+            if let Err(err) = &result
+                && unknown_key(err)
+            {
+                log::debug!("ID token signed with an unknown key, revalidating the key set");
+                let mtls = self.http.client_certificate.is_some();
+                let client = self.client(rediscover(&self.common.issuer, &http, mtls).await?);
+                let verifier = self.id_token_verifier(client.id_token_verifier());
+                result = id_token.clone().into_claims(&verifier, nonce);
+            }
+
+            result.context("failed to verify ID token")?;
+        }
+
+        Ok(token.into())
+    }
+
+    fn client(&self, provider_metadata: ExtendedProviderMetadata) -> FlowClient {
+        CoreClient::from_provider_metadata(
+            provider_metadata,
+            ClientId::new(self.client_id.clone()),
+            self.client_secret.clone().map(ClientSecret::new),
+        )
+    }
+
+    /// Accept the additional audiences of the requested scopes when verifying the ID token
+    fn id_token_verifier<'a>(
+        &'a self,
+        verifier: CoreIdTokenVerifier<'a>,
+    ) -> CoreIdTokenVerifier<'a> {
+        let scopes = self.common.scope.as_deref();
+        verifier.set_other_audience_verifier_fn(move |other| {
+            other_audiences(scopes).any(|aud| other == &aud)
+        })
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{
+    cmd::create::CreateCommon,
+    config::{ClientAuthentication, ClientType},
+    dpop::{DpopClient, DpopKey},
+    http::{HttpOptions, create_client},
+    oidc::{
+        CLIENT_ASSERTION_TYPE, ExtendedMetadata, ExtendedProviderMetadata, client_assertion,
+        discover, extra_scopes, other_audiences, push_authorization_request, rediscover,
+        unknown_key,
+    },
+    server::{Bind, Server},
+};
+use anyhow::{Context, bail};
+use oauth2::{
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet, EndpointNotSet,
+    EndpointSet, PkceCodeChallenge, RedirectUrl,
+};
+use openidconnect::{
+    AuthenticationFlow, Nonce,
+    core::{CoreClient, CoreIdTokenVerifier, CoreResponseType, CoreTokenResponse},
+};
+use url::Url;
+
+/// Options of the interactive login, using the authorization code flow
+#[derive(Clone, Debug, clap::Args)]
+pub struct CodeFlow {
+    /// Force using a specific port for the local server
+    #[arg(short, long)]
+    pub port: Option<u16>,
+
+    /// Open the link automatically
+    #[arg(short, long)]
+    pub open: bool,
+
+    /// Choose how to bind the local server
+    #[arg(short, long, env = "BIND_MODE", value_enum, default_value_t = Bind::Prefer6)]
+    pub bind: Bind,
+
+    /// Use IPv4 only binding (equivalent to --bind only4)
+    #[arg(short = '4', conflicts_with_all = ["bind", "only6"])]
+    pub only4: bool,
+
+    /// Use IPv6 only binding (equivalent to --bind only6)
+    #[arg(short = '6', conflicts_with = "bind")]
+    pub only6: bool,
+
+    /// Require the use of pushed authorization requests (RFC 9126)
+    #[arg(long)]
+    pub par: bool,
+
+    /// Don't use pushed authorization requests, unless required by the issuer
+    #[arg(long, conflicts_with = "par")]
+    pub no_par: bool,
+}
+
+pub type FlowClient = CoreClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+/// Create a client from the provider metadata, authenticating like the client type
+pub fn flow_client(provider_metadata: ExtendedProviderMetadata, r#type: &ClientType) -> FlowClient {
+    let client_secret = match r#type {
+        // with an assertion or mutual TLS, we must not send the secret
+        ClientType::Confidential { authentication, .. }
+        | ClientType::Exchanged { authentication, .. }
+            if *authentication != ClientAuthentication::ClientSecret =>
+        {
+            None
+        }
+        _ => r#type.client_secret(),
+    };
+
+    CoreClient::from_provider_metadata(
+        provider_metadata,
+        ClientId::new(r#type.client_id().to_string()),
+        client_secret.map(|secret| ClientSecret::new(secret.to_string())),
+    )
+}
+
+impl CodeFlow {
+    /// Log in a user interactively, using the authorization code flow
+    pub async fn login(
+        &self,
+        common: &CreateCommon,
+        r#type: &ClientType,
+        http: &HttpOptions,
+        dpop: Option<DpopKey>,
+    ) -> anyhow::Result<CoreTokenResponse> {
+        let http = http.clone().with_certificate(r#type.certificate());
+        let mtls = http.client_certificate.is_some();
+        let http = create_client(&http).await?;
+
+        let provider_metadata = discover(&common.issuer, &http, mtls).await?;
+        let par = self.par_endpoint(provider_metadata.additional_metadata())?;
+        let client = flow_client(provider_metadata, r#type);
+
+        let token_http = DpopClient::new(http.clone(), dpop);
+
+        let server = Server::new(self.bind_mode(), self.port).await?;
+        let redirect = format!("http://localhost:{}", server.port);
+
+        let client = client.set_redirect_uri(RedirectUrl::new(redirect)?);
+
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+
+        let req = client
+            .authorize_url(
+                AuthenticationFlow::<CoreResponseType>::AuthorizationCode,
+                CsrfToken::new_random,
+                Nonce::new_random,
+            )
+            .add_scopes(extra_scopes(common.scope.as_deref()));
+
+        let (open, csrf_token, nonce) = req.set_pkce_challenge(pkce_challenge).url();
+
+        let open = match par {
+            Some(endpoint) => {
+                log::debug!("Using pushed authorization request: {endpoint}");
+                push_authorization_request(&http, &endpoint, &open, r#type).await?
+            }
+            None => open,
+        };
+
+        println!(
+            r#"
+
+Open the following URL in your browser and perform the interactive login process (use --open to do this automatically):
+
+    {open}
+
+"#
+        );
+
+        if let Err(err) = open::that(open.to_string()) {
+            log::warn!(
+                "Failed to open URL in browser. You can still copy the link from the console. Error: {err}"
+            );
+        }
+
+        let result = server.receive_token().await?;
+
+        match result.state {
+            None => {
+                bail!("missing 'state' parameter from server");
+            }
+            Some(state) if &state != csrf_token.secret() => {
+                bail!("state mismatch");
+            }
+            Some(_) => {}
+        }
+
+        let mut request = client
+            .exchange_code(AuthorizationCode::new(result.code))?
+            .set_pkce_verifier(pkce_verifier);
+
+        if let Some(token_url) = client.token_uri()
+            && let Some(assertion) = client_assertion(r#type, token_url.as_str())?
+        {
+            request = request
+                .add_extra_param("client_assertion_type", CLIENT_ASSERTION_TYPE)
+                .add_extra_param("client_assertion", assertion);
+        }
+
+        let token = request.request_async(&token_http).await?;
+
+        if let Some(id_token) = token.extra_fields().id_token() {
+            let verifier = id_token_verifier(common, client.id_token_verifier());
+            let mut result = id_token.clone().into_claims(&verifier, &nonce);
+
+            if let Err(err) = &result
+                && unknown_key(err)
+            {
+                log::debug!("ID token signed with an unknown key, revalidating the key set");
+                let client = flow_client(rediscover(&common.issuer, &http, mtls).await?, r#type);
+                let verifier = id_token_verifier(common, client.id_token_verifier());
+                result = id_token.clone().into_claims(&verifier, &nonce);
+            }
+
+            result.context("failed to verify ID token")?;
+        }
+
+        Ok(token)
+    }
+
+    /// Evaluate the endpoint for pushed authorization requests, if they should be used
+    fn par_endpoint(&self, metadata: &ExtendedMetadata) -> anyhow::Result<Option<Url>> {
+        let endpoint = metadata.pushed_authorization_request_endpoint.clone();
+
+        if (self.par || metadata.require_pushed_authorization_requests) && endpoint.is_none() {
+            bail!("pushed authorization requests are required, but the issuer has no endpoint");
+        }
+
+        if self.no_par && !metadata.require_pushed_authorization_requests {
+            return Ok(None);
+        }
+
+        Ok(endpoint)
+    }
+
+    fn bind_mode(&self) -> Bind {
+        if self.only4 {
+            Bind::Only4
+        } else if self.only6 {
+            Bind::Only6
+        } else {
+            self.bind
+        }
+    }
+}
+
+/// Accept the additional audiences of the requested scopes when verifying the ID token
+fn id_token_verifier<'a>(
+    common: &'a CreateCommon,
+    verifier: CoreIdTokenVerifier<'a>,
+) -> CoreIdTokenVerifier<'a> {
+    let scopes = common.scope.as_deref();
+    verifier.set_other_audience_verifier_fn(move |other| {
+        other_audiences(scopes).any(|aud| other == &aud)
+    })
+}
@@ -1,7 +1,14 @@
 mod confidential;
-mod public;
+mod device;
+pub mod login;
+mod password;
+pub mod public;
 
-use crate::cmd::create::{confidential::CreateConfidential, public::CreatePublic};
+use crate::cmd::create::{
+    confidential::CreateConfidential, device::CreateDevice, password::CreatePassword,
+    public::CreatePublic,
+};
+use crate::dpop::DpopKey;
 use openidconnect::IssuerUrl;
 
 /// Create a new client
@@ -38,9 +45,24 @@ pub struct CreateCommon {
     /// Additional scope
     #[arg(short = 'S', long)]
     pub scope: Option<String>,
+
+    /// Bind tokens to a newly generated key, using DPoP (RFC 9449)
+    #[arg(long)]
+    pub dpop: bool,
+
+    /// Refresh tokens which are valid for less than this duration, by default
+    #[arg(long)]
+    pub min_valid: Option<humantime::Duration>,
+}
+
+impl CreateCommon {
+    /// Create a new DPoP key, if requested
+    pub fn dpop_key(&self) -> anyhow::Result<Option<DpopKey>> {
+        self.dpop.then(DpopKey::generate).transpose()
+    }
 }
 
-fn parse_issuer(s: &str) -> Result<IssuerUrl, anyhow::Error> {
+pub fn parse_issuer(s: &str) -> Result<IssuerUrl, anyhow::Error> {
     Ok(IssuerUrl::new(s.to_string())?)
 }
 
@@ -48,6 +70,8 @@ fn parse_issuer(s: &str) -> Result<IssuerUrl, anyhow::Error> {
 pub enum CreateType {
     Confidential(CreateConfidential),
     Public(CreatePublic),
+    Device(CreateDevice),
+    Password(CreatePassword),
 }
 
 impl CreateType {
@@ -55,6 +79,8 @@ impl CreateType {
         match self {
             Self::Confidential(cmd) => cmd.run().await,
             Self::Public(cmd) => cmd.run().await,
+            Self::Device(cmd) => cmd.run().await,
+            Self::Password(cmd) => cmd.run().await,
         }
     }
 }
@@ -0,0 +1,120 @@
+use crate::{
+    cmd::create::CreateCommon,
+    config::{Client, ClientType, Config, PasswordSource},
+    http::HttpOptions,
+    oidc::{TokenResult, get_token},
+    utils::OrNone,
+};
+use anyhow::{Context, bail};
+use std::path::PathBuf;
+
+/// Create a new client, using the resource owner password credentials grant
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct CreatePassword {
+    #[command(flatten)]
+    pub common: CreateCommon,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// The client ID
+    #[arg(short = 'i', long)]
+    pub client_id: String,
+
+    /// The client secret
+    #[arg(short = 's', long)]
+    pub client_secret: Option<String>,
+
+    /// The username
+    #[arg(short, long)]
+    pub username: String,
+
+    #[command(flatten)]
+    pub password: PasswordArgs,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+#[derive(Clone, Debug, clap::Args)]
+#[group(required = true, multiple = false)]
+pub struct PasswordArgs {
+    /// The password, stored in the configuration
+    #[arg(short = 'P', long)]
+    pub password: Option<String>,
+
+    /// Name of an environment variable to read the password from when requesting a token
+    #[arg(long)]
+    pub password_env: Option<String>,
+
+    /// A file to read the password from when requesting a token
+    #[arg(long)]
+    pub password_file: Option<PathBuf>,
+}
+
+impl From<PasswordArgs> for PasswordSource {
+    fn from(value: PasswordArgs) -> Self {
+        match (value.password, value.password_env, value.password_file) {
+            (Some(password), _, _) => Self::Value(password),
+            (_, Some(env), _) => Self::Env(env),
+            (_, _, Some(file)) => Self::File(file),
+            // clap ensures one of them is present, an empty password is the best we can do
+            (None, None, None) => Self::Value(Default::default()),
+        }
+    }
+}
+
+impl CreatePassword {
+    pub async fn run(self) -> anyhow::Result<()> {
+        log::debug!("creating new client: {}", self.common.name);
+
+        Config::locked(self.config.as_deref(), async |config| {
+            if !self.common.force && config.clients.contains_key(&self.common.name) {
+                bail!(
+                    "A client named '{}' already exists. You need to delete it first or use --force",
+                    self.common.name
+                );
+            }
+
+            let mut client = Client {
+                issuer_url: self.common.issuer.clone(),
+                scope: self.common.scope.clone(),
+                r#type: ClientType::Password {
+                    client_id: self.client_id.clone(),
+                    client_secret: self.client_secret.clone(),
+                    username: self.username.clone(),
+                    password: self.password.clone().into(),
+                },
+                state: None,
+                dpop: self.common.dpop_key()?,
+                registration: None,
+                min_valid: self.common.min_valid.map(Into::into),
+            };
+
+            if !self.common.skip_initial {
+                let token = get_token(&client, &self.http)
+                    .await
+                    .context("failed retrieving first token")?;
+
+                let token = match token {
+                    TokenResult::Refreshed(token) | TokenResult::Existing(token) => token,
+                };
+
+                log::info!("First token:");
+                log::info!("       ID: {}", OrNone(&token.id_token));
+                log::info!("   Access: {}", token.access_token);
+                log::info!("  Refresh: {}", OrNone(&token.refresh_token));
+
+                client.state = Some(token);
+            }
+
+            config
+                .clients
+                .insert(self.common.name.clone(), client.clone());
+
+            Ok(())
+        })
+        .await
+    }
+}
@@ -1,20 +1,17 @@
 use crate::{
-    cmd::create::CreateCommon,
+    cmd::create::{
+        CreateCommon,
+        login::{CodeFlow, flow_client},
+    },
     config::{Client, ClientType, Config},
+    dpop::{DpopClient, DpopKey},
     http::{HttpOptions, create_client},
-    oidc::{extra_scopes, other_audiences, refresh_token_request},
-    server::{Bind, Server},
+    oidc::{discover, refresh_token_request},
     utils::OrNone,
 };
-use anyhow::{Context, bail};
-use oauth2::{
-    AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet, EndpointNotSet,
-    EndpointSet, PkceCodeChallenge, RedirectUrl, TokenResponse,
-};
-use openidconnect::{
-    AuthenticationFlow, Nonce,
-    core::{CoreClient, CoreProviderMetadata, CoreResponseType, CoreTokenResponse},
-};
+use anyhow::bail;
+use oauth2::TokenResponse;
+use openidconnect::core::CoreTokenResponse;
 use std::path::PathBuf;
 
 /// Create a new public client
@@ -38,39 +35,13 @@ pub struct CreatePublic {
     #[arg(short = 'R', long)]
     pub refresh_token: Option<String>,
 
-    /// Force using a specific port for the local server
-    #[arg(short, long)]
-    pub port: Option<u16>,
-
-    /// Open the link automatically
-    #[arg(short, long)]
-    pub open: bool,
-
-    /// Choose how to bind the local server
-    #[arg(short, long, env = "BIND_MODE", value_enum, default_value_t = Bind::Prefer6)]
-    pub bind: Bind,
-
-    /// Use IPv4 only binding (equivalent to --bind only4)
-    #[arg(short = '4', conflicts_with_all = ["bind", "only6"])]
-    pub only4: bool,
-
-    /// Use IPv6 only binding (equivalent to --bind only6)
-    #[arg(short = '6', conflicts_with = "bind")]
-    pub only6: bool,
+    #[command(flatten)]
+    pub code_flow: CodeFlow,
 
     #[command(flatten)]
     pub http: HttpOptions,
 }
 
-type FlowClient = CoreClient<
-    EndpointSet,
-    EndpointNotSet,
-    EndpointNotSet,
-    EndpointNotSet,
-    EndpointMaybeSet,
-    EndpointMaybeSet,
->;
-
 impl CreatePublic {
     pub async fn run(self) -> anyhow::Result<()> {
         log::debug!("creating new client: {}", self.common.name);
@@ -83,29 +54,8 @@ impl CreatePublic {
                 );
             }
 
-            let http = create_client(&self.http).await?;
-
-            let provider_metadata =
-                CoreProviderMetadata::discover_async(self.common.issuer.clone(), &http).await?;
-
-            let client = CoreClient::from_provider_metadata(
-                provider_metadata,
-                ClientId::new(self.client_id.clone()),
-                self.client_secret.clone().map(ClientSecret::new),
-            );
-
-            let token = match &self.refresh_token {
-                None => self.code_flow(&http, &client).await?,
-                Some(refresh_token) => {
-                    refresh_token_request(
-                        &http,
-                        &client,
-                        self.common.scope.as_deref(),
-                        refresh_token.clone(),
-                    )
-                    .await?
-                }
-            };
+            let dpop = self.common.dpop_key()?;
+            let token = self.initial_token(dpop.clone()).await?;
 
             log::info!("First token:");
             log::info!(
@@ -127,11 +77,11 @@ impl CreatePublic {
             let client = Client {
                 issuer_url: self.common.issuer.clone(),
                 scope: self.common.scope.clone(),
-                r#type: ClientType::Public {
-                    client_id: self.client_id.clone(),
-                    client_secret: self.client_secret.clone(),
-                },
+                r#type: self.client_type(),
                 state: Some(token.into()),
+                dpop,
+                registration: None,
+                min_valid: self.common.min_valid.map(Into::into),
             };
 
             config
@@ -143,86 +93,40 @@ impl CreatePublic {
         .await
     }
 
-    fn bind_mode(&self) -> Bind {
-        if self.only4 {
-            Bind::Only4
-        } else if self.only6 {
-            Bind::Only6
-        } else {
-            self.bind
-        }
+    /// Perform the login, using the authorization code flow or the initial refresh token
+    pub async fn initial_token(&self, dpop: Option<DpopKey>) -> anyhow::Result<CoreTokenResponse> {
+        let Some(refresh_token) = &self.refresh_token else {
+            return self
+                .code_flow
+                .login(&self.common, &self.client_type(), &self.http, dpop)
+                .await;
+        };
+
+        let http = create_client(&self.http).await?;
+
+        let provider_metadata = discover(
+            &self.common.issuer,
+            &http,
+            self.http.client_certificate.is_some(),
+        )
+        .await?;
+
+        let client = flow_client(provider_metadata, &self.client_type());
+
+        refresh_token_request(
+            &DpopClient::new(http, dpop),
+            &client,
+            self.common.scope.as_deref(),
+            None,
+            refresh_token.clone(),
+        )
+        .await
     }
 
-    async fn code_flow(
-        &self,
-        http: &reqwest::Client,
-        client: &FlowClient,
-    ) -> anyhow::Result<CoreTokenResponse> {
-        let server = Server::new(self.bind_mode(), self.port).await?;
-        let redirect = format!("http://localhost:{}", server.port);
-
-        let client = client.clone().set_redirect_uri(RedirectUrl::new(redirect)?);
-
-        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
-
-        let req = client
-            .authorize_url(
-                AuthenticationFlow::<CoreResponseType>::AuthorizationCode,
-                CsrfToken::new_random,
-                Nonce::new_random,
-            )
-            .add_scopes(extra_scopes(self.common.scope.as_deref()));
-
-        let (open, csrf_token, nonce) = req.set_pkce_challenge(pkce_challenge).url();
-
-        println!(
-            r#"
-
-Open the following URL in your browser and perform the interactive login process (use --open to do this automatically):
-
-    {open}
-
-"#
-        );
-
-        if let Err(err) = open::that(open.to_string()) {
-            log::warn!(
-                "Failed to open URL in browser. You can still copy the link from the console. Error: {err}"
-            );
+    fn client_type(&self) -> ClientType {
+        ClientType::Public {
+            client_id: self.client_id.clone(),
+            client_secret: self.client_secret.clone(),
         }
-
-        let result = server.receive_token().await?;
-
-        match result.state {
-            None => {
-                bail!("missing 'state' parameter from server");
-            }
-            Some(state) if &state != csrf_token.secret() => {
-                bail!("state mismatch");
-            }
-            Some(_) => {}
-        }
-
-        let token = client
-            .exchange_code(AuthorizationCode::new(result.code))?
-            .set_pkce_verifier(pkce_verifier)
-            .request_async(http)
-            .await?;
-
-        if let Some(id_token) = token.extra_fields().id_token() {
-            let scopes = self.common.scope.as_deref();
-            let verifier =
-                client
-                    .id_token_verifier()
-                    .set_other_audience_verifier_fn(move |other| {
-                        other_audiences(scopes).any(|aud| other == &aud)
-                    });
-            id_token
-                .clone()
-                .into_claims(&verifier, &nonce)
-                .context("failed to verify ID token")?;
-        }
-
-        Ok(token)
     }
 }
@@ -1,4 +1,9 @@
-use crate::config::Config;
+use crate::{
+    config::Config,
+    http::{HttpOptions, create_client},
+    logout::revoke,
+    oidc::discover,
+};
 use std::path::PathBuf;
 
 /// Delete a client
@@ -9,6 +14,13 @@ pub struct Delete {
 
     #[arg(from_global)]
     pub config: Option<PathBuf>,
+
+    /// Revoke the tokens of the client before deleting it
+    #[arg(long)]
+    pub revoke: bool,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
 }
 
 impl Delete {
@@ -16,11 +28,35 @@ impl Delete {
         log::debug!("deleting client: {}", self.name);
 
         Config::locked(self.config.as_deref(), async |config| {
+            if self.revoke
+                && let Some(client) = config.clients.get(&self.name)
+            {
+                let http = self
+                    .http
+                    .clone()
+                    .with_certificate(client.r#type.certificate());
+                let mtls = http.client_certificate.is_some();
+                let http = create_client(&http).await?;
+                // the client is deleted, even if the issuer can't be reached
+                let revoked = match discover(&client.issuer_url, &http, mtls).await {
+                    Ok(metadata) => revoke(&http, &metadata, client).await,
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = revoked {
+                    log::warn!(
+                        "Failed to revoke tokens, they stay valid until they expire: {err:#}"
+                    );
+                }
+            }
+
             if config.clients.remove(&self.name).is_some() {
                 log::info!("deleted client: {}", self.name);
             } else {
                 log::info!("client did not exist: {}", self.name);
             }
+            config
+                .registries
+                .retain(|_, registry| registry.client != self.name);
             Ok(())
         })
         .await
@@ -0,0 +1,230 @@
+use crate::{
+    config::{Config, Registry},
+    http::HttpOptions,
+    oidc::get_valid_token,
+};
+use anyhow::{anyhow, bail};
+use std::{collections::BTreeMap, path::PathBuf, process::ExitCode};
+use tokio::io::{AsyncReadExt, stdin};
+
+/// The message expected by Docker, in case no credentials are available
+const NOT_FOUND: &str = "credentials not found in native keychain";
+
+/// Provide tokens to Docker, acting as a credential helper
+///
+/// When invoked as `docker-credential-oidc`, this command is used implicitly.
+#[derive(Debug, clap::Parser)]
+pub struct DockerCredential {
+    #[command(subcommand)]
+    pub command: DockerCommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum DockerCommand {
+    /// Provide the credentials for a registry, as requested by Docker
+    Get(Operation),
+    /// Store credentials, which is a no-op, as the tokens are managed by the client
+    Store(Operation),
+    /// Erase the credentials of a registry, marking the access token as expired
+    Erase(Operation),
+    /// List all registries
+    List(Operation),
+    /// Use a client for authenticating with a registry
+    Add(Add),
+    /// Stop using a client for a registry
+    Remove(Remove),
+}
+
+impl DockerCredential {
+    pub async fn run(self) -> anyhow::Result<ExitCode> {
+        match self.command {
+            DockerCommand::Get(cmd) => return cmd.get().await,
+            DockerCommand::Store(cmd) => cmd.store().await,
+            DockerCommand::Erase(cmd) => cmd.erase().await,
+            DockerCommand::List(cmd) => cmd.list().await,
+            DockerCommand::Add(cmd) => cmd.run().await,
+            DockerCommand::Remove(cmd) => cmd.run().await,
+        }
+        .map(|()| ExitCode::SUCCESS)
+    }
+}
+
+/// An operation of the credential helper protocol, reading its input from stdin
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct Operation {
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Credentials {
+    #[serde(rename = "ServerURL")]
+    server_url: String,
+    username: String,
+    secret: String,
+}
+
+impl Operation {
+    async fn get(&self) -> anyhow::Result<ExitCode> {
+        let server_url = read_input().await?;
+        let host = registry_host(&server_url);
+
+        let credentials = Config::locked(self.config.as_deref(), async |config| {
+            let Some(registry) = config.registries.get(&host).cloned() else {
+                return Ok(None);
+            };
+
+            let client = config
+                .by_name_mut(&registry.client)
+                .ok_or_else(|| anyhow!("unknown client '{}'", registry.client))?;
+
+            let state = get_valid_token(client, &self.http, None, false).await?;
+
+            Ok(Some(Credentials {
+                server_url: server_url.clone(),
+                username: registry.username,
+                secret: state.access_token,
+            }))
+        })
+        .await?;
+
+        match credentials {
+            Some(credentials) => {
+                println!("{}", serde_json::to_string(&credentials)?);
+                Ok(ExitCode::SUCCESS)
+            }
+            None => {
+                println!("{NOT_FOUND}");
+                Ok(ExitCode::FAILURE)
+            }
+        }
+    }
+
+    async fn store(&self) -> anyhow::Result<()> {
+        // consume the credentials, but keep using the tokens of the client
+        let _ = read_input().await?;
+        log::debug!("ignoring credentials to store");
+
+        Ok(())
+    }
+
+    async fn erase(&self) -> anyhow::Result<()> {
+        let host = registry_host(&read_input().await?);
+
+        Config::locked(self.config.as_deref(), async |config| {
+            let Some(registry) = config.registries.get(&host).cloned() else {
+                return Ok(());
+            };
+
+            if let Some(client) = config.by_name_mut(&registry.client) {
+                client.expire_access_token();
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list(&self) -> anyhow::Result<()> {
+        let config = Config::load(self.config.as_deref())?;
+
+        let registries: BTreeMap<_, _> = config
+            .registries
+            .into_iter()
+            .map(|(host, registry)| (host, registry.username))
+            .collect();
+
+        println!("{}", serde_json::to_string(&registries)?);
+
+        Ok(())
+    }
+}
+
+/// Use a client for authenticating with a registry
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct Add {
+    /// The host of the registry (e.g. `registry.example.com`)
+    pub registry: String,
+
+    /// Name of the client
+    pub client: String,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// The username to provide, together with the access token as password
+    #[arg(long, default_value = "oauth2")]
+    pub username: String,
+}
+
+impl Add {
+    async fn run(self) -> anyhow::Result<()> {
+        Config::locked(self.config.as_deref(), async |config| {
+            if !config.clients.contains_key(&self.client) {
+                bail!("unknown client '{}'", self.client);
+            }
+
+            let host = registry_host(&self.registry);
+            config.registries.insert(
+                host.clone(),
+                Registry {
+                    client: self.client.clone(),
+                    username: self.username.clone(),
+                },
+            );
+
+            log::info!("Using client '{}' for registry '{host}'", self.client);
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Stop using a client for a registry
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct Remove {
+    /// The host of the registry
+    pub registry: String,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+}
+
+impl Remove {
+    async fn run(self) -> anyhow::Result<()> {
+        Config::locked(self.config.as_deref(), async |config| {
+            let host = registry_host(&self.registry);
+            if config.registries.remove(&host).is_none() {
+                bail!("unknown registry '{host}'");
+            }
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Read the input of the credential helper protocol
+async fn read_input() -> anyhow::Result<String> {
+    let mut input = String::new();
+    stdin().read_to_string(&mut input).await?;
+    Ok(input.trim().to_string())
+}
+
+/// Get the host (and port) of a registry, which Docker may provide as URL
+fn registry_host(server_url: &str) -> String {
+    let host = server_url
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(server_url);
+
+    host.split('/').next().unwrap_or(host).to_lowercase()
+}
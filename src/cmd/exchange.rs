@@ -0,0 +1,196 @@
+use crate::{
+    config::{Client, Config},
+    exchange::{self, ExchangeRequest, parse_token_type},
+    http::{HttpOptions, create_client},
+    oidc::{client_assertion, discover, get_valid_token},
+    utils::inspect::inspect,
+};
+use anyhow::{anyhow, bail};
+use std::path::PathBuf;
+
+/// Exchange the token of a client for a different one (RFC 8693)
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct Exchange {
+    /// Name of the client providing the subject token
+    pub name: String,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// Use the ID token as subject token, instead of the access token
+    #[arg(short, long)]
+    pub id: bool,
+
+    /// The logical name of the target service
+    #[arg(short, long)]
+    pub audience: Vec<String>,
+
+    /// The URI of the target service
+    #[arg(long)]
+    pub resource: Vec<String>,
+
+    /// Scope of the requested token
+    #[arg(short = 'S', long)]
+    pub scope: Option<String>,
+
+    /// The type of the requested token (e.g. "access_token", "id_token", "jwt", or a URI)
+    #[arg(long, value_parser(parse_token_type))]
+    pub requested_token_type: Option<String>,
+
+    /// A token representing the acting party
+    #[arg(long)]
+    pub actor_token: Option<String>,
+
+    /// The type of the actor token
+    #[arg(
+        long,
+        value_parser(parse_token_type),
+        default_value = "access_token",
+        requires = "actor_token"
+    )]
+    pub actor_token_type: String,
+
+    /// Store the exchanged token as a new client, which can be refreshed by name. Requires the
+    /// exchange to issue a refresh token.
+    #[arg(long)]
+    pub store: Option<String>,
+
+    /// Overwrite an existing client when using 'store'
+    #[arg(long, requires = "store")]
+    pub force: bool,
+
+    /// Prefix with "Bearer ", for using it as a `Authorization` header value
+    #[arg(short, long)]
+    pub bearer: bool,
+
+    /// Suitable for using directly with HTTPie as header
+    #[arg(short = 'H', long, conflicts_with = "bearer")]
+    pub header: bool,
+
+    /// Inspect the token
+    #[arg(short = 'I', long, conflicts_with = "bearer")]
+    pub inspect: bool,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+impl Exchange {
+    pub async fn run(self) -> anyhow::Result<()> {
+        let (token, refreshable) = Config::locked(self.config.as_deref(), async |config| {
+            if let Some(store) = &self.store
+                && !self.force
+                && config.clients.contains_key(store)
+            {
+                bail!(
+                    "A client named '{store}' already exists. You need to delete it first or use --force"
+                );
+            }
+
+            let client = config
+                .by_name_mut(&self.name)
+                .ok_or_else(|| anyhow!("unknown client '{}'", self.name))?;
+
+            let state = get_valid_token(client, &self.http, None, false).await?;
+
+            let (subject_token, subject_token_type) = match self.id {
+                true => (
+                    state
+                        .id_token
+                        .ok_or_else(|| anyhow!("ID token not available"))?,
+                    exchange::ID_TOKEN.to_string(),
+                ),
+                false => (state.access_token, exchange::ACCESS_TOKEN.to_string()),
+            };
+
+            let request = ExchangeRequest {
+                subject_token,
+                subject_token_type,
+                actor_token: self
+                    .actor_token
+                    .clone()
+                    .map(|token| (token, self.actor_token_type.clone())),
+                audience: self.audience.clone(),
+                resource: self.resource.clone(),
+                scope: self.scope.clone(),
+                requested_token_type: self.requested_token_type.clone(),
+            };
+
+            let http = self
+                .http
+                .clone()
+                .with_certificate(client.r#type.certificate());
+            let mtls = http.client_certificate.is_some();
+            let http = create_client(&http).await?;
+            let provider_metadata = discover(&client.issuer_url, &http, mtls).await?;
+            let token_url = provider_metadata
+                .token_endpoint()
+                .ok_or_else(|| anyhow!("issuer has no token endpoint"))?
+                .url();
+            let assertion = client_assertion(&client.r#type, token_url.as_str())?;
+
+            let response = exchange::exchange(
+                &http,
+                token_url,
+                client.r#type.client_id(),
+                client.r#type.client_secret(),
+                assertion.as_deref(),
+                &request,
+            )
+            .await?;
+
+            log::info!("Issued token type: {}", response.issued_token_type);
+
+            let token = response.access_token.clone();
+
+            // without a refresh token, the stored client couldn't get a new token
+            let refreshable = response.refresh_token.is_some();
+
+            if let Some(store) = &self.store
+                && refreshable
+            {
+                let derived = Client {
+                    issuer_url: client.issuer_url.clone(),
+                    scope: self.scope.clone(),
+                    // refreshing requires the same authentication as the original client
+                    r#type: client.r#type.exchanged(),
+                    state: Some(response.into()),
+                    dpop: client.dpop.clone(),
+                    registration: None,
+                    min_valid: None,
+                };
+                config.clients.insert(store.clone(), derived);
+            }
+
+            Ok((token, refreshable))
+        })
+        .await?;
+
+        if let Some(store) = &self.store
+            && !refreshable
+        {
+            // the state of the original client was stored, as it might have been refreshed
+            bail!(
+                "The exchange didn't issue a refresh token, unable to store it as client '{store}'"
+            );
+        }
+
+        match (self.bearer, self.header, self.inspect) {
+            (true, _, _) => {
+                println!("Bearer {token}");
+            }
+            (_, true, _) => {
+                println!("Authorization:Bearer {token}");
+            }
+            (_, _, true) => {
+                inspect(token)?;
+            }
+            _ => {
+                println!("{token}");
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{config::Config, http::HttpOptions, oidc::get_valid_token};
+use anyhow::{Context, anyhow};
+use std::{ffi::OsString, path::PathBuf, process::ExitCode};
+
+/// Run a command, providing a valid token through environment variables
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct Exec {
+    /// Name of the client
+    pub name: String,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// Force a new token
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// Refresh the token if it expires within this duration, guaranteeing a minimum lifetime.
+    /// Overrides the default of the client.
+    #[arg(long, alias = "min-valid")]
+    pub refresh_before: Option<humantime::Duration>,
+
+    /// Name of the variable receiving the access token, empty to skip
+    #[arg(long, default_value = "OIDC_ACCESS_TOKEN")]
+    pub access_token_env: String,
+
+    /// Name of the variable receiving the ID token, empty to skip
+    #[arg(long, default_value = "OIDC_ID_TOKEN")]
+    pub id_token_env: String,
+
+    /// Name of the variable receiving the value of the `Authorization` header, empty to skip
+    #[arg(long, default_value = "OIDC_AUTHORIZATION")]
+    pub header_env: String,
+
+    /// The command to run, and its arguments
+    #[arg(last = true, required = true)]
+    pub command: Vec<OsString>,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+impl Exec {
+    pub async fn run(self) -> anyhow::Result<ExitCode> {
+        let (state, dpop) = Config::locked(self.config.as_deref(), async |config| {
+            let client = config
+                .by_name_mut(&self.name)
+                .ok_or_else(|| anyhow!("unknown client '{}'", self.name))?;
+
+            let min_valid = self.refresh_before.map(Into::into);
+            let state = get_valid_token(client, &self.http, min_valid, self.force).await?;
+
+            Ok((state, client.dpop.is_some()))
+        })
+        .await?;
+
+        let token_type = match dpop {
+            true => "DPoP",
+            false => "Bearer",
+        };
+
+        let env = [
+            (&self.access_token_env, Some(state.access_token.clone())),
+            (&self.id_token_env, state.id_token.clone()),
+            (
+                &self.header_env,
+                Some(format!("{token_type} {}", state.access_token)),
+            ),
+        ];
+
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| anyhow!("missing command"))?;
+
+        let mut command = std::process::Command::new(program);
+        command.args(args);
+        for (name, value) in env {
+            match (name.is_empty(), value) {
+                (false, Some(value)) => command.env(name, value),
+                // don't leak an outdated value of the calling environment
+                (false, None) => command.env_remove(name),
+                (true, _) => continue,
+            };
+        }
+
+        log::debug!("Running: {:?}", self.command);
+
+        run(command, program)
+    }
+}
+
+/// Replace the current process, so that signals and the exit code are handled by the command
+#[cfg(unix)]
+fn run(mut command: std::process::Command, program: &OsString) -> anyhow::Result<ExitCode> {
+    use std::os::unix::process::CommandExt;
+
+    let err = command.exec();
+    Err(err).with_context(|| format!("failed to run {}", program.to_string_lossy()))
+}
+
+/// Run the command as a child process, passing on its exit code
+#[cfg(not(unix))]
+fn run(mut command: std::process::Command, program: &OsString) -> anyhow::Result<ExitCode> {
+    let status = command
+        .status()
+        .with_context(|| format!("failed to run {}", program.to_string_lossy()))?;
+
+    // exit codes which can't be passed on must not turn into success by truncating them
+    Ok(match status.code().map(u8::try_from) {
+        Some(Ok(code)) => ExitCode::from(code),
+        Some(Err(_)) | None => ExitCode::FAILURE,
+    })
+}
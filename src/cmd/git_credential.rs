@@ -0,0 +1,120 @@
+use crate::{config::Config, http::HttpOptions, oidc::get_valid_token};
+use anyhow::anyhow;
+use std::path::PathBuf;
+use tokio::io::{AsyncBufReadExt, BufReader, stdin};
+
+/// The operation requested by git
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Operation {
+    /// Provide the credentials
+    Get,
+    /// Store credentials, which is a no-op, as the tokens are managed by the client
+    Store,
+    /// Erase the credentials, marking the access token as expired
+    Erase,
+}
+
+/// Provide tokens to git, acting as a credential helper
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct GitCredential {
+    /// Name of the client
+    pub name: String,
+
+    /// The operation, as requested by git
+    #[arg(value_enum)]
+    pub operation: Operation,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// The username to provide, together with the access token as password
+    #[arg(long, default_value = "oauth2")]
+    pub username: String,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+impl GitCredential {
+    pub async fn run(self) -> anyhow::Result<()> {
+        let request = read_request().await?;
+        log::debug!("credential request: {request:?}");
+
+        match self.operation {
+            Operation::Get => self.get(&request).await,
+            Operation::Store => Ok(()),
+            Operation::Erase => self.erase().await,
+        }
+    }
+
+    async fn get(&self, request: &[(String, String)]) -> anyhow::Result<()> {
+        let state = Config::locked(self.config.as_deref(), async |config| {
+            let client = config
+                .by_name_mut(&self.name)
+                .ok_or_else(|| anyhow!("unknown client '{}'", self.name))?;
+
+            get_valid_token(client, &self.http, None, false).await
+        })
+        .await?;
+
+        let expiry = state
+            .expiration()
+            .map(|expires| expires.unix_timestamp().to_string());
+
+        // git 2.46 and later can use the token directly, without a username
+        let authtype = request
+            .iter()
+            .any(|(key, value)| key == "capability[]" && value == "authtype");
+
+        let mut response = vec![];
+        if authtype {
+            response.push(("capability[]", "authtype".to_string()));
+            response.push(("authtype", "Bearer".to_string()));
+            response.push(("credential", state.access_token.clone()));
+            response.push(("ephemeral", "true".to_string()));
+        } else {
+            response.push(("username", self.username.clone()));
+            response.push(("password", state.access_token.clone()));
+            if let Some(expiry) = expiry {
+                response.push(("password_expiry_utc", expiry));
+            }
+        }
+
+        for (key, value) in response {
+            println!("{key}={value}");
+        }
+
+        Ok(())
+    }
+
+    /// The token was rejected, expire it so that the next request fetches a new one
+    async fn erase(&self) -> anyhow::Result<()> {
+        Config::locked(self.config.as_deref(), async |config| {
+            config
+                .by_name_mut(&self.name)
+                .ok_or_else(|| anyhow!("unknown client '{}'", self.name))?
+                .expire_access_token();
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Read the attributes, as provided by git, until an empty line
+async fn read_request() -> anyhow::Result<Vec<(String, String)>> {
+    let mut request = vec![];
+
+    let mut lines = BufReader::new(stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            request.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    Ok(request)
+}
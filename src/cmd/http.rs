@@ -0,0 +1,179 @@
+use crate::{
+    http::{HttpOptions, create_client},
+    tokens::TokenSource,
+};
+use anyhow::{Context, anyhow};
+use colored_json::to_colored_json_auto;
+use reqwest::{Method, StatusCode, header::HeaderMap};
+use serde_json::Value;
+use std::{
+    io::{Read, Write, stdout},
+    path::PathBuf,
+    process::ExitCode,
+};
+use url::Url;
+
+/// Perform an HTTP request, authenticated using the token of a client
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct Http {
+    /// Name of the client
+    pub name: String,
+
+    /// The HTTP method (e.g. GET, POST)
+    #[arg(value_parser = parse_method)]
+    pub method: Method,
+
+    /// The URL of the request
+    pub url: Url,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// Additional headers (`name:value`), can be repeated
+    #[arg(short = 'H', long, value_parser = parse_header)]
+    pub header: Vec<(String, String)>,
+
+    /// The request body, use `@<file>` to read it from a file, or `@-` from stdin. JSON data will
+    /// be sent as `application/json`, unless a content type is set.
+    #[arg(short, long)]
+    pub data: Option<String>,
+
+    /// Print the status and headers of the response
+    #[arg(short, long)]
+    pub include: bool,
+
+    /// Print the body of the response as it is
+    #[arg(long)]
+    pub raw: bool,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+fn parse_method(value: &str) -> anyhow::Result<Method> {
+    Ok(Method::from_bytes(value.to_uppercase().as_bytes())?)
+}
+
+fn parse_header(value: &str) -> anyhow::Result<(String, String)> {
+    let (name, value) = value
+        .split_once(':')
+        .ok_or_else(|| anyhow!("header must be 'name:value'"))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+impl Http {
+    pub async fn run(self) -> anyhow::Result<ExitCode> {
+        let body = self.body()?;
+
+        let tokens = TokenSource::new(
+            self.config.clone(),
+            self.name.clone(),
+            self.http.clone(),
+            None,
+        )
+        .await?;
+        let http = create_client(tokens.http()).await?;
+
+        let token = tokens.token().await;
+        let mut response = self.send(&http, &tokens, &token, &body).await?;
+
+        if invalid_token(response.status(), response.headers()) {
+            tokens.renew(&token).await?;
+            let token = tokens.token().await;
+            response = self.send(&http, &tokens, &token, &body).await?;
+        }
+
+        let status = response.status();
+        if self.include {
+            println!("{:?} {status}", response.version());
+            for (name, value) in response.headers() {
+                println!("{name}: {}", value.to_str().unwrap_or_default());
+            }
+            println!();
+        }
+
+        let json = response
+            .headers()
+            .get("Content-Type")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("json"));
+        let data = response.bytes().await?;
+
+        match serde_json::from_slice::<Value>(&data) {
+            Ok(value) if json && !self.raw => println!("{}", to_colored_json_auto(&value)?),
+            _ => stdout().lock().write_all(&data)?,
+        }
+
+        Ok(match status.is_success() {
+            true => ExitCode::SUCCESS,
+            false => {
+                log::warn!("Request failed: {status}");
+                ExitCode::FAILURE
+            }
+        })
+    }
+
+    async fn send(
+        &self,
+        http: &reqwest::Client,
+        tokens: &TokenSource,
+        token: &str,
+        body: &Option<Vec<u8>>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let (authorization, proof) =
+            tokens.authorization(token, self.method.as_str(), &self.url)?;
+
+        let mut request = http
+            .request(self.method.clone(), self.url.clone())
+            .header("Authorization", authorization);
+        if let Some(proof) = proof {
+            request = request.header("DPoP", proof);
+        }
+
+        let content_type = self
+            .header
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("content-type"));
+        for (name, value) in &self.header {
+            request = request.header(name, value);
+        }
+
+        if let Some(body) = body {
+            if !content_type && serde_json::from_slice::<Value>(body).is_ok() {
+                request = request.header("Content-Type", "application/json");
+            }
+            request = request.body(body.clone());
+        }
+
+        Ok(request.send().await?)
+    }
+
+    fn body(&self) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(data) = &self.data else {
+            return Ok(None);
+        };
+
+        Ok(Some(match data.strip_prefix('@') {
+            Some("-") => {
+                let mut data = vec![];
+                std::io::stdin().read_to_end(&mut data)?;
+                data
+            }
+            Some(file) => {
+                std::fs::read(file).with_context(|| format!("unable to read file: {file}"))?
+            }
+            None => data.as_bytes().to_vec(),
+        }))
+    }
+}
+
+/// Check if the request was rejected because of an invalid (e.g. expired or revoked) token
+fn invalid_token(status: StatusCode, headers: &HeaderMap) -> bool {
+    status == StatusCode::UNAUTHORIZED
+        && headers
+            .get_all("WWW-Authenticate")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| value.contains("invalid_token"))
+}
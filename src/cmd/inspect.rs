@@ -1,16 +1,78 @@
-use crate::utils::inspect::inspect;
+use crate::{
+    claims::print_claims,
+    config::Config,
+    http::{ClientCertificate, HttpOptions, create_client},
+    jose::verify_remote,
+    oidc::discover_metadata,
+    utils::{
+        inspect::{inspect, inspect_binding},
+        verify::{Check, Expectations, Outcome, check_claims, report},
+    },
+};
+use anyhow::anyhow;
+use biscuit::CompactPart;
+use openidconnect::IssuerUrl;
+use serde_json::Value;
+use std::{collections::HashMap, path::PathBuf, process::ExitCode};
 use tokio::io::{AsyncBufReadExt, BufReader, stdin};
+use url::Url;
+
+/// Exit code in case a token failed verification
+const INVALID_TOKEN: u8 = 3;
 
 /// Inspect tokens
 #[derive(Debug, clap::Parser)]
 pub struct Inspect {
     /// The tokens to inspect, if none is present it will read from stdin.
     pub token: Vec<String>,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// Check the certificate binding (`cnf.x5t#S256`) of the tokens against a certificate (PEM)
+    #[arg(long)]
+    pub certificate: Option<PathBuf>,
+
+    /// Print only the value of a claim of the tokens, using a JSON pointer or a dotted path (e.g.
+    /// `realm_access.roles`). Can be repeated. Fails if the claim is absent.
+    #[arg(long, conflicts_with_all = ["certificate", "verify"])]
+    pub claim: Vec<String>,
+
+    /// Print string values of claims without quotes
+    #[arg(long, requires = "claim")]
+    pub raw: bool,
+
+    /// Verify the signature and claims of the tokens, exits with code 3 if a token is invalid
+    #[arg(long)]
+    pub verify: bool,
+
+    /// The expected issuer, instead of using the `iss` claim of the token
+    #[arg(long, requires = "verify", conflicts_with = "client")]
+    pub issuer: Option<String>,
+
+    /// Use the issuer of a configured client
+    #[arg(long, requires = "verify")]
+    pub client: Option<String>,
+
+    /// Tolerated clock skew when checking timestamps
+    #[arg(long, default_value = "60s", requires = "verify")]
+    pub leeway: humantime::Duration,
+
+    /// The expected audience
+    #[arg(long, requires = "verify")]
+    pub audience: Option<String>,
+
+    /// The expected authorized party
+    #[arg(long, requires = "verify")]
+    pub azp: Option<String>,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
 }
 
 impl Inspect {
-    pub async fn run(self) -> anyhow::Result<()> {
-        let mut tokens = self.token;
+    pub async fn run(self) -> anyhow::Result<ExitCode> {
+        let mut tokens = self.token.clone();
 
         if tokens.is_empty() {
             let mut lines = BufReader::new(stdin()).lines();
@@ -19,12 +81,119 @@ impl Inspect {
             }
         }
 
+        if !self.claim.is_empty() {
+            for token in tokens {
+                print_claims(&token, &self.claim, self.raw)?;
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        let thumbprint = self
+            .certificate
+            .clone()
+            .map(|certificate| {
+                ClientCertificate {
+                    certificate,
+                    key: None,
+                    password: None,
+                }
+                .thumbprint()
+            })
+            .transpose()?;
+
+        let issuer = match &self.client {
+            Some(name) => {
+                let config = Config::load(self.config.as_deref())?;
+                let client = config
+                    .clients
+                    .get(name)
+                    .ok_or_else(|| anyhow!("unknown client '{name}'"))?;
+                Some(client.issuer_url.to_string())
+            }
+            None => self.issuer.clone(),
+        };
+
+        let mut verifier = Verifier {
+            http: create_client(&self.http).await?,
+            jwks_uris: Default::default(),
+        };
+        let mut valid = true;
+
         for (n, token) in tokens.into_iter().enumerate() {
             log::debug!("Inspecting token: {token}");
             println!("Token #{n}:");
-            inspect(token)?;
+            if let Some(thumbprint) = &thumbprint {
+                inspect_binding(&token, thumbprint)?;
+            }
+            inspect(token.clone())?;
+
+            if self.verify {
+                let checks = verifier.verify(&token, issuer.clone(), &self).await;
+                valid &= report(&checks);
+            }
+        }
+
+        Ok(match valid {
+            true => ExitCode::SUCCESS,
+            false => ExitCode::from(INVALID_TOKEN),
+        })
+    }
+}
+
+/// Verifies tokens, caching the key set locations of the issuers
+struct Verifier {
+    http: reqwest::Client,
+    jwks_uris: HashMap<String, Url>,
+}
+
+impl Verifier {
+    async fn verify(&mut self, token: &str, issuer: Option<String>, args: &Inspect) -> Vec<Check> {
+        let payload = biscuit::Compact::decode(token)
+            .parts
+            .get(1)
+            .and_then(|part| part.to_bytes().ok())
+            .and_then(|data| serde_json::from_slice::<Value>(&data).ok());
+
+        let Some(payload) = payload else {
+            return vec![Check::new("format", Outcome::Failed("not a JWT".into()))];
+        };
+
+        let issuer = issuer.or_else(|| payload["iss"].as_str().map(ToString::to_string));
+
+        let signature = match &issuer {
+            None => Outcome::Failed("unable to find keys, unknown issuer".into()),
+            Some(issuer) => match self.jwks_uri(issuer).await {
+                Err(err) => Outcome::Failed(format!("unable to fetch keys: {err}")),
+                Ok(url) => match verify_remote(&self.http, token, &url).await {
+                    Ok(_) => Outcome::Passed("verified using the keys of the issuer".into()),
+                    Err(err) => Outcome::Failed(format!("{err:#}")),
+                },
+            },
+        };
+
+        let expected = Expectations {
+            issuer,
+            audience: args.audience.clone(),
+            azp: args.azp.clone(),
+            leeway: (*args.leeway).try_into().unwrap_or(time::Duration::MAX),
+        };
+
+        let mut checks = vec![Check::new("signature", signature)];
+        checks.extend(check_claims(&payload, &expected));
+        checks
+    }
+
+    async fn jwks_uri(&mut self, issuer: &str) -> anyhow::Result<Url> {
+        if let Some(url) = self.jwks_uris.get(issuer) {
+            return Ok(url.clone());
         }
 
-        Ok(())
+        // only the key set is needed, which is loaded in the format required for verifying
+        let metadata =
+            discover_metadata(&IssuerUrl::new(issuer.to_string())?, &self.http, false).await?;
+        let url = metadata.jwks_uri().url().clone();
+        self.jwks_uris.insert(issuer.to_string(), url.clone());
+
+        Ok(url)
     }
 }
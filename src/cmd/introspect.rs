@@ -0,0 +1,123 @@
+use crate::{
+    config::Config,
+    http::{HttpOptions, create_client},
+    oidc::{authenticate, discover, endpoint_error},
+    utils::truncated,
+};
+use anyhow::anyhow;
+use colored_json::to_colored_json_auto;
+use serde_json::Value;
+use std::{path::PathBuf, process::ExitCode};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+/// Introspect a token using the issuer (RFC 7662)
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct Introspect {
+    /// Name of the client to authenticate with
+    pub name: String,
+
+    /// The token to introspect, defaults to the current token of the client
+    pub token: Option<String>,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// Introspect the refresh token of the client, instead of the access token
+    #[arg(short, long, conflicts_with = "token")]
+    pub refresh: bool,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+impl Introspect {
+    pub async fn run(self) -> anyhow::Result<ExitCode> {
+        let config = Config::load(self.config.as_deref())?;
+        let client = config
+            .clients
+            .get(&self.name)
+            .ok_or_else(|| anyhow!("unknown client '{}'", self.name))?;
+
+        let (token, token_type_hint) = match &self.token {
+            Some(token) => (token.clone(), None),
+            None => {
+                let state = client
+                    .state
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("client '{}' has no token", self.name))?;
+                match self.refresh {
+                    true => (
+                        state
+                            .refresh_token
+                            .clone()
+                            .ok_or_else(|| anyhow!("refresh token not available"))?,
+                        Some("refresh_token"),
+                    ),
+                    false => (state.access_token.clone(), Some("access_token")),
+                }
+            }
+        };
+
+        let http = self
+            .http
+            .clone()
+            .with_certificate(client.r#type.certificate());
+        let mtls = http.client_certificate.is_some();
+        let http = create_client(&http).await?;
+        let metadata = discover(&client.issuer_url, &http, mtls).await?;
+
+        let endpoint = metadata
+            .additional_metadata()
+            .introspection_endpoint
+            .clone()
+            .ok_or_else(|| anyhow!("issuer does not support token introspection"))?;
+        let audience = metadata
+            .token_endpoint()
+            .map(|url| url.as_str())
+            .unwrap_or_else(|| metadata.issuer().as_str());
+
+        let mut form = vec![("token", token)];
+        if let Some(token_type_hint) = token_type_hint {
+            form.push(("token_type_hint", token_type_hint.to_string()));
+        }
+
+        let request = authenticate(http.post(endpoint), &mut form, &client.r#type, audience)?;
+        let response = request
+            .header("Accept", "application/json")
+            .form(&form)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(endpoint_error("token introspection", response).await);
+        }
+
+        let response: Value = serde_json::from_slice(&response.bytes().await?)?;
+
+        println!("{}", to_colored_json_auto(&response)?);
+        println!();
+
+        let active = response["active"].as_bool().unwrap_or_default();
+        println!("Active: {}", if active { "yes" } else { "no" });
+
+        if let Some(exp) = response["exp"]
+            .as_i64()
+            .and_then(|exp| OffsetDateTime::from_unix_timestamp(exp).ok())
+        {
+            let rem = exp - OffsetDateTime::now_utc();
+            let format_rem = truncated(rem);
+            let exp = exp.format(&Rfc3339)?;
+
+            match rem.is_positive() {
+                true => println!("Expires: {exp} (valid: {format_rem})"),
+                false => println!("Expires: {exp} (expired: {format_rem})"),
+            }
+        }
+
+        Ok(match active {
+            true => ExitCode::SUCCESS,
+            false => ExitCode::FAILURE,
+        })
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{
+    claims::expiration,
+    config::Config,
+    http::HttpOptions,
+    kube::{API_VERSION_V1, ExecCredential, ExecInfo},
+    oidc::{TokenResult, fetch_token, get_token_valid_for},
+};
+use anyhow::anyhow;
+use std::path::PathBuf;
+use time::OffsetDateTime;
+
+/// Provide a token to `kubectl`, acting as a client-go credential plugin
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct KubeCredential {
+    /// Name of the client
+    pub name: String,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// Provide the access token, instead of the ID token
+    #[arg(long)]
+    pub access_token: bool,
+
+    /// Refresh the token if it is valid for less than this duration, overrides the default of
+    /// the client
+    #[arg(long)]
+    pub min_valid: Option<humantime::Duration>,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+impl KubeCredential {
+    pub async fn run(self) -> anyhow::Result<()> {
+        // prefer the version requested by the client
+        let api_version = ExecInfo::from_env()?
+            .map(|info| info.api_version)
+            .unwrap_or_else(|| API_VERSION_V1.to_string());
+
+        let (token, expires) = Config::locked(self.config.as_deref(), async |config| {
+            let client = config
+                .by_name_mut(&self.name)
+                .ok_or_else(|| anyhow!("unknown client '{}'", self.name))?;
+
+            let min_valid = self
+                .min_valid
+                .map(Into::into)
+                .or(client.min_valid)
+                .unwrap_or_default();
+            let mut token = get_token_valid_for(client, &self.http, Some(min_valid)).await?;
+
+            // the ID token may expire before the access token
+            if !self.access_token
+                && let TokenResult::Existing(state) = &token
+                && state
+                    .id_token
+                    .as_deref()
+                    .and_then(expiration)
+                    .is_some_and(|expires| expires <= OffsetDateTime::now_utc() + min_valid)
+            {
+                token = fetch_token(client, &self.http).await?;
+            }
+
+            let state = token.store(&mut client.state);
+
+            match self.access_token {
+                true => Ok((state.access_token.clone(), state.expiration())),
+                false => {
+                    let token = state
+                        .id_token
+                        .ok_or_else(|| anyhow!("ID token not available"))?;
+                    let expires = expiration(&token).or(state.expires);
+                    Ok((token, expires))
+                }
+            }
+        })
+        .await?;
+
+        let credential = ExecCredential::new(api_version, token, expires);
+        println!("{}", serde_json::to_string_pretty(&credential)?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{
+    config::Config,
+    kube::{API_VERSION_V1, Kubeconfig, default_kubeconfig},
+};
+use anyhow::{anyhow, bail};
+use serde_yaml::{Mapping, Value};
+use std::path::PathBuf;
+
+/// Add a user to a kubeconfig file, using `oidc kube-credential` for authentication
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct KubeconfigCommand {
+    /// Name of the client
+    pub name: String,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// The kubeconfig file to modify, defaults to the one used by kubectl
+    #[arg(long)]
+    pub kubeconfig: Option<PathBuf>,
+
+    /// The name of the cluster (from the kubeconfig), creates a context for the user and the
+    /// cluster
+    #[arg(long)]
+    pub cluster: Option<String>,
+
+    /// The name of the user, defaults to `oidc-<client>`
+    #[arg(long)]
+    pub user: Option<String>,
+
+    /// The name of the context, defaults to `<cluster>-<user>`
+    #[arg(long, requires = "cluster")]
+    pub context: Option<String>,
+
+    /// Switch to the context
+    #[arg(long, requires = "cluster")]
+    pub use_context: bool,
+
+    /// Provide the access token, instead of the ID token
+    #[arg(long)]
+    pub access_token: bool,
+
+    /// The command to run, defaults to the current executable
+    #[arg(long)]
+    pub command: Option<String>,
+}
+
+impl KubeconfigCommand {
+    pub async fn run(self) -> anyhow::Result<()> {
+        let config = Config::load(self.config.as_deref())?;
+        if !config.clients.contains_key(&self.name) {
+            bail!("unknown client '{}'", self.name);
+        }
+
+        let path = self
+            .kubeconfig
+            .clone()
+            .or_else(default_kubeconfig)
+            .ok_or_else(|| anyhow!("unable to evaluate kubeconfig file, use --kubeconfig"))?;
+        log::debug!("updating kubeconfig: {}", path.display());
+
+        let mut kubeconfig = Kubeconfig::load(&path)?;
+
+        let user = self
+            .user
+            .clone()
+            .unwrap_or_else(|| format!("oidc-{}", self.name));
+        kubeconfig.upsert("users", &user, "user", self.user_entry()?)?;
+        log::info!("Added user '{user}'");
+
+        if let Some(cluster) = &self.cluster {
+            if !kubeconfig.contains("clusters", cluster) {
+                log::warn!("Cluster '{cluster}' is not present in the kubeconfig file");
+            }
+
+            let context = self
+                .context
+                .clone()
+                .unwrap_or_else(|| format!("{cluster}-{user}"));
+
+            let mut entry = Mapping::new();
+            entry.insert("cluster".into(), cluster.as_str().into());
+            entry.insert("user".into(), user.as_str().into());
+            kubeconfig.upsert("contexts", &context, "context", Value::Mapping(entry))?;
+            log::info!("Added context '{context}'");
+
+            if self.use_context {
+                kubeconfig.set_current_context(&context);
+            }
+        }
+
+        kubeconfig.store(&path)
+    }
+
+    /// The `user` of a `users[]` entry
+    fn user_entry(&self) -> anyhow::Result<Value> {
+        let command = match &self.command {
+            Some(command) => command.clone(),
+            None => std::env::current_exe()?.to_string_lossy().to_string(),
+        };
+
+        let mut args = vec![];
+        if let Some(config) = &self.config {
+            args.push("--config".to_string());
+            args.push(std::path::absolute(config)?.to_string_lossy().to_string());
+        }
+        args.extend(["kube-credential".to_string(), self.name.clone()]);
+        if self.access_token {
+            args.push("--access-token".into());
+        }
+
+        let mut exec = Mapping::new();
+        exec.insert("apiVersion".into(), API_VERSION_V1.into());
+        exec.insert("command".into(), command.into());
+        exec.insert(
+            "args".into(),
+            Value::Sequence(args.into_iter().map(Value::from).collect()),
+        );
+        // refreshing tokens doesn't require any interaction
+        exec.insert("interactiveMode".into(), "Never".into());
+        exec.insert("provideClusterInfo".into(), false.into());
+
+        let mut user = Mapping::new();
+        user.insert("exec".into(), Value::Mapping(exec));
+
+        Ok(Value::Mapping(user))
+    }
+}
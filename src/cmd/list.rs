@@ -1,12 +1,27 @@
 use crate::{
-    claims::{AccessTokenClaims, RefreshTokenClaims},
-    config::{ClientType, Config},
+    claims::expiration,
+    config::{Client, ClientType, Config},
+    utils::truncated,
 };
-use biscuit::{CompactJson, Empty, jws::Compact};
+use anyhow::bail;
 use comfy_table::{Cell, CellAlignment, Color, ContentArrangement, Row, Table, presets};
-use std::path::PathBuf;
+use std::{path::PathBuf, str::FromStr};
 use time::{OffsetDateTime, macros::format_description};
 
+/// Output format of the list
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Output {
+    /// A table
+    #[default]
+    Table,
+    /// A table, including all details
+    Wide,
+    /// JSON records
+    Json,
+    /// YAML records
+    Yaml,
+}
+
 /// List configured clients
 #[derive(Debug, clap::Parser)]
 #[command(rename_all_env = "SNAKE_CASE")]
@@ -17,105 +32,303 @@ pub struct List {
     /// Show more details
     #[arg(short, long)]
     pub details: bool,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t)]
+    pub output: Output,
+
+    /// Only show clients matching an expression (`<key>=<value>` or `<key>!=<value>`), can be
+    /// repeated. Keys: issuer, type, status, access, refresh, id
+    #[arg(long)]
+    pub filter: Vec<Filter>,
+}
+
+/// The type of the client
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Confidential,
+    Public,
+    Password,
+    Exchanged,
+}
+
+/// The overall status of the client
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// No token was retrieved yet
+    None,
+    /// The access token is valid
+    Valid,
+    /// The access token is expired, but can be refreshed
+    Refreshable,
+    /// All tokens are expired
+    Expired,
+}
+
+/// The status of a single token
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenStatus {
+    Valid,
+    Expired,
+}
+
+/// Information about a token, not containing the token itself
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct TokenInfo {
+    pub status: TokenStatus,
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expires: Option<OffsetDateTime>,
+}
+
+impl TokenInfo {
+    fn new(expires: Option<OffsetDateTime>) -> Self {
+        let status = match expires {
+            Some(expires) if expires <= OffsetDateTime::now_utc() => TokenStatus::Expired,
+            _ => TokenStatus::Valid,
+        };
+
+        Self { status, expires }
+    }
+}
+
+/// A record describing a client, excluding any secrets
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Record {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub r#type: Kind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<TokenInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<TokenInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<TokenInfo>,
+}
+
+impl Record {
+    fn new(name: String, client: &Client) -> Self {
+        let r#type = match &client.r#type {
+            ClientType::Confidential { .. } => Kind::Confidential,
+            ClientType::Public { .. } => Kind::Public,
+            ClientType::Password { .. } => Kind::Password,
+            ClientType::Exchanged { .. } => Kind::Exchanged,
+        };
+
+        let state = client.state.as_ref();
+
+        // prefer the expiration of the token, fall back to the one reported by the issuer
+        let access_token =
+            state.map(|state| TokenInfo::new(expiration(&state.access_token).or(state.expires)));
+        let refresh_token = state
+            .and_then(|state| state.refresh_token.as_deref())
+            .map(|token| TokenInfo::new(expiration(token)));
+        let id_token = state
+            .and_then(|state| state.id_token.as_deref())
+            .map(|token| TokenInfo::new(expiration(token)));
+
+        let status = match (&access_token, &refresh_token) {
+            (None, _) => Status::None,
+            (Some(access), _) if access.status == TokenStatus::Valid => Status::Valid,
+            (_, Some(refresh)) if refresh.status == TokenStatus::Valid => Status::Refreshable,
+            _ => Status::Expired,
+        };
+
+        Self {
+            name,
+            issuer: client.issuer_url.to_string(),
+            client_id: client.r#type.client_id().to_string(),
+            r#type,
+            scope: client.scope.clone(),
+            status,
+            access_token,
+            refresh_token,
+            id_token,
+        }
+    }
+}
+
+/// The key of a filter expression
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FilterKey {
+    Issuer,
+    Type,
+    Status,
+    Access,
+    Refresh,
+    Id,
+}
+
+/// A filter expression, matching a field of a record
+#[derive(Clone, Debug)]
+pub struct Filter {
+    key: FilterKey,
+    negate: bool,
+    value: String,
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, negate, value) = match s.split_once("!=") {
+            Some((key, value)) => (key, true, value),
+            None => match s.split_once('=') {
+                Some((key, value)) => (key, false, value),
+                None => bail!("filter must be '<key>=<value>' or '<key>!=<value>'"),
+            },
+        };
+
+        let (key, values): (_, &[&str]) = match key.trim() {
+            "issuer" => (FilterKey::Issuer, &[]),
+            "type" => (
+                FilterKey::Type,
+                &["confidential", "public", "password", "exchanged"],
+            ),
+            "status" => (
+                FilterKey::Status,
+                &["none", "valid", "refreshable", "expired"],
+            ),
+            "access" => (FilterKey::Access, &["none", "valid", "expired"]),
+            "refresh" => (FilterKey::Refresh, &["none", "valid", "expired"]),
+            "id" => (FilterKey::Id, &["none", "valid", "expired"]),
+            key => bail!("unknown filter key: {key}"),
+        };
+
+        let value = value.trim().to_string();
+        if !values.is_empty() && !values.contains(&value.as_str()) {
+            bail!(
+                "invalid value '{value}', must be one of: {}",
+                values.join(", ")
+            );
+        }
+
+        Ok(Self { key, negate, value })
+    }
+}
+
+impl Filter {
+    fn matches(&self, record: &Record) -> bool {
+        let token = |info: &Option<TokenInfo>| match info {
+            None => "none".to_string(),
+            Some(info) => name(&info.status),
+        };
+
+        let actual = match self.key {
+            FilterKey::Issuer => record.issuer.clone(),
+            FilterKey::Type => name(&record.r#type),
+            FilterKey::Status => name(&record.status),
+            FilterKey::Access => token(&record.access_token),
+            FilterKey::Refresh => token(&record.refresh_token),
+            FilterKey::Id => token(&record.id_token),
+        };
+
+        (actual == self.value) != self.negate
+    }
+}
+
+/// The serialized name of an enum value
+fn name(value: &impl serde::Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(ToString::to_string))
+        .unwrap_or_default()
 }
 
 impl List {
     pub async fn run(self) -> anyhow::Result<()> {
         let config = Config::load(self.config.as_deref())?;
 
+        let records: Vec<_> = config
+            .clients
+            .iter()
+            .map(|(name, client)| Record::new(name.clone(), client))
+            .filter(|record| self.filter.iter().all(|filter| filter.matches(record)))
+            .collect();
+
+        match self.output {
+            Output::Table => println!("{}", self.table(&records, false)),
+            Output::Wide => println!("{}", self.table(&records, true)),
+            Output::Json => println!("{}", serde_json::to_string_pretty(&records)?),
+            Output::Yaml => print!("{}", serde_yaml::to_string(&records)?),
+        }
+
+        Ok(())
+    }
+
+    fn table(&self, records: &[Record], wide: bool) -> Table {
         let mut table = Table::new();
         table
             .load_preset(presets::ASCII_MARKDOWN)
-            .set_content_arrangement(ContentArrangement::Dynamic)
-            .set_header([
-                "Name",
-                "Issuer",
-                "Client",
-                "Public",
-                "Access Token",
-                "Refresh Token",
-            ]);
-
-        for (name, client) in config.clients {
+            .set_content_arrangement(ContentArrangement::Dynamic);
+
+        let mut header = vec!["Name", "Issuer", "Client", "Public"];
+        if wide {
+            header.extend(["Type", "Scope", "Status"]);
+        }
+        header.extend(["Access Token", "Refresh Token"]);
+        if wide {
+            header.push("ID Token");
+        }
+        table.set_header(header);
+
+        for record in records {
             let mut row = Row::new();
-            row.add_cell(name.into());
-            row.add_cell(client.issuer_url.to_string().into());
+            row.add_cell(record.name.as_str().into());
+            row.add_cell(record.issuer.as_str().into());
+            row.add_cell(record.client_id.as_str().into());
 
-            match &client.r#type {
-                ClientType::Public { client_id, .. } => {
-                    row.add_cell(client_id.into());
+            match record.r#type {
+                Kind::Public => {
                     row.add_cell(Cell::from("X").set_alignment(CellAlignment::Center));
                 }
-                ClientType::Confidential { client_id, .. } => {
-                    row.add_cell(client_id.into());
+                Kind::Confidential | Kind::Password | Kind::Exchanged => {
                     row.add_cell("".into());
                 }
             }
 
-            if let Some(state) = &client.state {
-                let access = Self::token::<_, AccessTokenClaims>(&state.access_token, |token| {
-                    self.expiration(
-                        token
-                            .exp
-                            .and_then(|exp| OffsetDateTime::from_unix_timestamp(exp).ok()),
-                    )
-                });
-
-                row.add_cell(access);
-
-                let refresh = state
-                    .refresh_token
-                    .as_ref()
-                    .map(|refresh| {
-                        Self::token::<_, RefreshTokenClaims>(refresh, |token| {
-                            self.expiration(
-                                token
-                                    .exp
-                                    .and_then(|exp| OffsetDateTime::from_unix_timestamp(exp).ok()),
-                            )
-                        })
-                    })
-                    .unwrap_or_else(|| Cell::new(""));
-
-                row.add_cell(refresh);
+            if wide {
+                row.add_cell(name(&record.r#type).into());
+                row.add_cell(record.scope.as_deref().unwrap_or_default().into());
+                row.add_cell(name(&record.status).into());
+            }
+
+            row.add_cell(self.token(&record.access_token, wide));
+            row.add_cell(self.token(&record.refresh_token, wide));
+            if wide {
+                row.add_cell(self.token(&record.id_token, wide));
             }
 
             table.add_row(row);
         }
 
-        println!("{table}");
-
-        Ok(())
+        table
     }
 
-    /// Decode a token and call the function to extract cell information
-    ///
-    /// NOTE: The token is not being verified.
-    fn token<F, T>(token: &str, f: F) -> Cell
-    where
-        F: FnOnce(T) -> Cell,
-        T: CompactJson,
-    {
-        let token = match Compact::<T, Empty>::new_encoded(token).unverified_payload() {
-            Ok(token) => token,
-            Err(err) => return Cell::new(err.to_string()).fg(Color::Red),
-        };
-
-        f(token)
+    fn token(&self, info: &Option<TokenInfo>, wide: bool) -> Cell {
+        match info {
+            None => Cell::new(""),
+            Some(info) => self.expiration(info.expires, wide),
+        }
     }
 
-    fn expiration(&self, expires: Option<OffsetDateTime>) -> Cell {
+    fn expiration(&self, expires: Option<OffsetDateTime>, wide: bool) -> Cell {
         match expires {
             None => "∞".into(),
             Some(expires) => {
                 let rem = expires - OffsetDateTime::now_utc();
+                let format_rem = truncated(rem);
 
-                // truncate to seconds
-                let format_rem = humantime::Duration::from(std::time::Duration::from_secs(
-                    rem.unsigned_abs().as_secs(),
-                ));
-
-                let details = match self.details {
+                let details = match self.details || wide {
                     true => match expires.format(format_description!(
                         " ([year]-[month]-[day] [hour]:[minute]:[second]Z)"
                     )) {
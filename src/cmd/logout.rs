@@ -0,0 +1,104 @@
+use crate::{
+    config::Config,
+    http::{HttpOptions, create_client},
+    logout::{end_session_url, revoke},
+    oidc::discover,
+};
+use anyhow::anyhow;
+use std::path::PathBuf;
+use url::Url;
+
+/// Log out a client, revoking its tokens and clearing the local state
+#[derive(Debug, clap::Parser)]
+pub struct Logout {
+    /// The name of the client to log out
+    pub name: String,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// Don't revoke the tokens, only clear the local state
+    #[arg(long)]
+    pub no_revoke: bool,
+
+    /// End the session at the issuer too, using the browser (RP-initiated logout)
+    #[arg(short, long)]
+    pub end_session: bool,
+
+    /// The URI to redirect to after ending the session
+    #[arg(long, requires = "end_session")]
+    pub post_logout_redirect_uri: Option<Url>,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+impl Logout {
+    pub async fn run(self) -> anyhow::Result<()> {
+        log::debug!("logging out client: {}", self.name);
+
+        let end_session = Config::locked(self.config.as_deref(), async |config| {
+            let client = config
+                .by_name_mut(&self.name)
+                .ok_or_else(|| anyhow!("unknown client '{}'", self.name))?;
+
+            let http = self
+                .http
+                .clone()
+                .with_certificate(client.r#type.certificate());
+            let mtls = http.client_certificate.is_some();
+            let http = create_client(&http).await?;
+            // the local state is cleared, even if the issuer can't be reached
+            let metadata = discover(&client.issuer_url, &http, mtls).await;
+
+            if !self.no_revoke {
+                let revoked = match &metadata {
+                    Ok(metadata) => revoke(&http, metadata, client).await,
+                    Err(err) => Err(anyhow!("{err:#}")),
+                };
+                if let Err(err) = revoked {
+                    log::warn!(
+                        "Failed to revoke tokens, they stay valid until they expire: {err:#}"
+                    );
+                }
+            }
+
+            let end_session = match self.end_session {
+                true => match metadata.and_then(|metadata| {
+                    end_session_url(&metadata, client, self.post_logout_redirect_uri.as_ref())
+                }) {
+                    Ok(url) => Some(url),
+                    Err(err) => {
+                        log::warn!("Unable to end the session at the issuer: {err:#}");
+                        None
+                    }
+                },
+                false => None,
+            };
+
+            client.state = None;
+            log::info!("cleared state of client: {}", self.name);
+
+            Ok(end_session)
+        })
+        .await?;
+
+        if let Some(url) = end_session {
+            println!(
+                r#"
+Open the following URL in your browser to end the session:
+
+    {url}
+"#
+            );
+
+            if let Err(err) = open::that(url.as_str()) {
+                log::warn!(
+                    "Failed to open URL in browser. You can still copy the link from the console. Error: {err}"
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
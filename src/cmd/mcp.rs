@@ -55,7 +55,7 @@ impl OidcMcpServer {
         for (name, client) in &config.clients {
             let issuer = client.issuer_url.as_str();
             let status = match &client.state {
-                Some(state) => match state.expires {
+                Some(state) => match state.expiration() {
                     Some(exp) if exp > time::OffsetDateTime::now_utc() => "valid".to_string(),
                     Some(exp) => format!("expired ({})", exp),
                     None => "unknown expiry".to_string(),
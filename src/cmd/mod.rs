@@ -1,22 +1,53 @@
+#[cfg(unix)]
+mod agent;
+mod cache;
 mod completion;
 mod create;
 mod delete;
+mod docker_credential;
+mod exchange;
+mod exec;
+mod git_credential;
+mod http;
 mod inspect;
+mod introspect;
+mod kube_credential;
+mod kubeconfig;
 mod list;
+mod logout;
 #[cfg(feature = "mcp")]
 mod mcp;
+mod proxy;
+mod register;
 mod token;
+mod userinfo;
 
 use std::process::ExitCode;
 
 #[derive(Debug, clap::Subcommand)]
 #[allow(clippy::large_enum_variant)]
 pub enum Command {
+    #[cfg(unix)]
+    Agent(agent::AgentCommand),
+    Cache(cache::Cache),
     Create(create::Create),
     Delete(delete::Delete),
+    DockerCredential(docker_credential::DockerCredential),
     Token(token::GetToken),
+    Exchange(exchange::Exchange),
+    Exec(exec::Exec),
+    GitCredential(git_credential::GitCredential),
+    Http(http::Http),
+    Register(register::Register),
     List(list::List),
+    #[command(name = "userinfo")]
+    UserInfo(userinfo::UserInfo),
+    Logout(logout::Logout),
+    Proxy(proxy::Proxy),
     Inspect(inspect::Inspect),
+    Introspect(introspect::Introspect),
+    KubeCredential(kube_credential::KubeCredential),
+    Kubeconfig(kubeconfig::KubeconfigCommand),
     Completion(completion::GetCompletion),
     #[cfg(feature = "mcp")]
     Mcp(mcp::Mcp),
@@ -25,11 +56,26 @@ pub enum Command {
 impl Command {
     pub async fn run(self) -> anyhow::Result<ExitCode> {
         match self {
+            #[cfg(unix)]
+            Self::Agent(cmd) => cmd.run().await,
+            Self::Cache(cmd) => cmd.run().await,
             Self::Create(cmd) => cmd.run().await,
             Self::Delete(cmd) => cmd.run().await,
+            Self::DockerCredential(cmd) => return cmd.run().await,
             Self::Token(cmd) => cmd.run().await,
+            Self::Exchange(cmd) => cmd.run().await,
+            Self::Exec(cmd) => return cmd.run().await,
+            Self::GitCredential(cmd) => cmd.run().await,
+            Self::Http(cmd) => return cmd.run().await,
+            Self::Register(cmd) => cmd.run().await,
             Self::List(cmd) => cmd.run().await,
-            Self::Inspect(cmd) => cmd.run().await,
+            Self::UserInfo(cmd) => cmd.run().await,
+            Self::Logout(cmd) => cmd.run().await,
+            Self::Proxy(cmd) => cmd.run().await,
+            Self::Inspect(cmd) => return cmd.run().await,
+            Self::Introspect(cmd) => return cmd.run().await,
+            Self::KubeCredential(cmd) => cmd.run().await,
+            Self::Kubeconfig(cmd) => cmd.run().await,
             Self::Completion(cmd) => cmd.run().await,
             #[cfg(feature = "mcp")]
             Self::Mcp(cmd) => cmd.run().await,
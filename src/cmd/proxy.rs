@@ -0,0 +1,50 @@
+use crate::{
+    http::{HttpOptions, create_client},
+    proxy::run,
+    tokens::TokenSource,
+};
+use std::{net::SocketAddr, path::PathBuf};
+use url::Url;
+
+/// Run a local reverse proxy, adding the token of a client to all requests
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct Proxy {
+    /// Name of the client
+    pub name: String,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// The address to listen on
+    #[arg(short, long, default_value = "127.0.0.1:8080")]
+    pub listen: SocketAddr,
+
+    /// The URL of the upstream server
+    #[arg(short, long)]
+    pub upstream: Url,
+
+    /// Refresh the token when it is valid for less than this duration, overrides the default of
+    /// the client [default: 30s]
+    #[arg(long)]
+    pub min_valid: Option<humantime::Duration>,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+impl Proxy {
+    pub async fn run(self) -> anyhow::Result<()> {
+        let tokens = TokenSource::new(
+            self.config.clone(),
+            self.name.clone(),
+            self.http.clone(),
+            self.min_valid.map(Into::into),
+        )
+        .await?;
+
+        let http = create_client(tokens.http()).await?;
+
+        run(self.listen, self.upstream, http, tokens).await
+    }
+}
@@ -0,0 +1,330 @@
+use crate::{
+    cmd::create::{CreateCommon, login::CodeFlow, parse_issuer, public::CreatePublic},
+    config::{Client, ClientAuthentication, ClientState, ClientType, Config},
+    http::{HttpOptions, create_client},
+    oidc::{TokenResult, discover, get_token},
+    registration::{self, ClientMetadata, RegistrationResponse},
+    server::Bind,
+    utils::OrNone,
+};
+use anyhow::{Context, anyhow, bail};
+use openidconnect::IssuerUrl;
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// Register a new client with the issuer, using dynamic client registration (RFC 7591)
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct Register {
+    /// Name of the client, used to locally identify it
+    pub name: String,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// URL of the issuer
+    #[arg(long, value_parser(parse_issuer), required_unless_present_any = ["update", "delete"])]
+    pub issuer: Option<IssuerUrl>,
+
+    /// Overwrite and existing client with the same name
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// An initial access token, in case the issuer requires one for registering clients
+    #[arg(long, env = "OIDC_INITIAL_ACCESS_TOKEN")]
+    pub initial_access_token: Option<String>,
+
+    /// Register a confidential client (client credentials grant), instead of a public client
+    #[arg(long)]
+    pub confidential: bool,
+
+    /// A human-readable name of the client, shown by the issuer
+    #[arg(long)]
+    pub client_name: Option<String>,
+
+    /// Additional scope
+    #[arg(short = 'S', long)]
+    pub scope: Option<String>,
+
+    /// Port of the loopback redirect URI of a public client, defaults to a free port
+    #[arg(short, long, conflicts_with = "confidential")]
+    pub port: Option<u16>,
+
+    /// Skip fetching the initial token
+    #[arg(long)]
+    pub skip_initial: bool,
+
+    /// Update the registration of an existing client (RFC 7592)
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "issuer",
+            "force",
+            "initial_access_token",
+            "confidential",
+            "skip_initial",
+            "delete",
+        ]
+    )]
+    pub update: bool,
+
+    /// Delete the registration of an existing client (RFC 7592), and the client itself
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "issuer",
+            "force",
+            "initial_access_token",
+            "confidential",
+            "skip_initial",
+            "client_name",
+            "scope",
+            "port",
+        ]
+    )]
+    pub delete: bool,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+impl Register {
+    pub async fn run(self) -> anyhow::Result<()> {
+        match (self.update, self.delete) {
+            (true, _) => self.update().await,
+            (_, true) => self.delete().await,
+            _ => self.register().await,
+        }
+    }
+
+    async fn register(&self) -> anyhow::Result<()> {
+        log::debug!("registering new client: {}", self.name);
+
+        let issuer = self
+            .issuer
+            .clone()
+            .ok_or_else(|| anyhow!("missing issuer URL"))?;
+
+        Config::locked(self.config.as_deref(), async |config| {
+            if !self.force && config.clients.contains_key(&self.name) {
+                bail!(
+                    "A client named '{}' already exists. You need to delete it first or use --force",
+                    self.name
+                );
+            }
+
+            let http = create_client(&self.http).await?;
+            let metadata =
+                discover(&issuer, &http, self.http.client_certificate.is_some()).await?;
+            let endpoint = metadata
+                .registration_endpoint()
+                .ok_or_else(|| anyhow!("issuer does not support dynamic client registration"))?
+                .url();
+
+            let port = match (self.confidential, self.port) {
+                (true, _) => None,
+                (false, Some(port)) => Some(port),
+                // find a free port, which will be used for the login as well
+                (false, None) => {
+                    let acceptor = Bind::default().into_acceptor(0).await?;
+                    Some(acceptor.local_addr()?.port())
+                }
+            };
+
+            let mut request = match port {
+                None => ClientMetadata::confidential(),
+                Some(port) => ClientMetadata::public(redirect_uri(port)),
+            };
+            request.client_name = self.client_name.clone();
+            request.scope = self.scope.as_deref().map(registration_scope);
+
+            let initial_access_token = self.initial_access_token.as_deref();
+            let response =
+                registration::register(&http, endpoint, initial_access_token, &request).await?;
+
+            log::info!("Registered client: {}", response.client_id);
+            if response.registration().is_none() {
+                log::warn!("Issuer does not support managing the registered client");
+            }
+
+            let mut client = Client {
+                issuer_url: issuer.clone(),
+                scope: self.scope.clone(),
+                r#type: match self.confidential {
+                    true => ClientType::Confidential {
+                        client_id: response.client_id.clone(),
+                        client_secret: Some(response.client_secret.clone().ok_or_else(|| {
+                            anyhow!("issuer did not provide a secret for the confidential client")
+                        })?),
+                        authentication: ClientAuthentication::ClientSecret,
+                        interactive: false,
+                    },
+                    false => ClientType::Public {
+                        client_id: response.client_id.clone(),
+                        client_secret: response.client_secret.clone(),
+                    },
+                },
+                state: None,
+                dpop: None,
+                registration: response.registration(),
+                min_valid: None,
+            };
+
+            if !self.skip_initial {
+                let token = match port {
+                    None => match get_token(&client, &self.http)
+                        .await
+                        .context("failed retrieving first token")?
+                    {
+                        TokenResult::Refreshed(token) | TokenResult::Existing(token) => token,
+                    },
+                    Some(port) => self
+                        .login(&issuer, &response, port)
+                        .await
+                        .context("failed retrieving first token")?,
+                };
+
+                log::info!("First token:");
+                log::info!("       ID: {}", OrNone(&token.id_token));
+                log::info!("   Access: {}", token.access_token);
+                log::info!("  Refresh: {}", OrNone(&token.refresh_token));
+
+                client.state = Some(token);
+            }
+
+            config.clients.insert(self.name.clone(), client);
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Perform the login of a newly registered public client
+    async fn login(
+        &self,
+        issuer: &IssuerUrl,
+        response: &RegistrationResponse,
+        port: u16,
+    ) -> anyhow::Result<ClientState> {
+        let create = CreatePublic {
+            common: CreateCommon {
+                name: self.name.clone(),
+                force: true,
+                skip_initial: false,
+                issuer: issuer.clone(),
+                scope: self.scope.clone(),
+                dpop: false,
+                min_valid: None,
+            },
+            config: self.config.clone(),
+            client_id: response.client_id.clone(),
+            client_secret: response.client_secret.clone(),
+            refresh_token: None,
+            code_flow: CodeFlow {
+                port: Some(port),
+                open: true,
+                bind: Bind::default(),
+                only4: false,
+                only6: false,
+                par: false,
+                no_par: false,
+            },
+            http: self.http.clone(),
+        };
+
+        Ok(create.initial_token(None).await?.into())
+    }
+
+    async fn update(&self) -> anyhow::Result<()> {
+        log::debug!("updating registration of client: {}", self.name);
+
+        Config::locked(self.config.as_deref(), async |config| {
+            let client = config
+                .by_name_mut(&self.name)
+                .ok_or_else(|| anyhow!("unknown client '{}'", self.name))?;
+            let registration = client
+                .registration
+                .clone()
+                .ok_or_else(|| anyhow!("client '{}' was not registered dynamically", self.name))?;
+
+            let http = create_client(&self.http).await?;
+            let mut metadata = registration::read(&http, &registration).await?;
+
+            if let Some(client_name) = &self.client_name {
+                metadata.insert("client_name".into(), client_name.clone().into());
+            }
+            if let Some(scope) = &self.scope {
+                metadata.insert("scope".into(), registration_scope(scope).into());
+                client.scope = Some(scope.clone());
+            }
+            if let Some(port) = self.port {
+                metadata.insert(
+                    "redirect_uris".into(),
+                    Value::Array(vec![redirect_uri(port).into()]),
+                );
+            }
+
+            let response = registration::update(&http, &registration, metadata).await?;
+
+            match &mut client.r#type {
+                ClientType::Confidential { client_secret, .. }
+                | ClientType::Public { client_secret, .. }
+                | ClientType::Password { client_secret, .. }
+                | ClientType::Exchanged { client_secret, .. } => {
+                    if response.client_secret.is_some() {
+                        *client_secret = response.client_secret.clone();
+                    }
+                }
+            }
+            // the registration access token might have been rotated
+            if let Some(registration) = response.registration() {
+                client.registration = Some(registration);
+            }
+
+            log::info!("updated registration of client: {}", self.name);
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete(&self) -> anyhow::Result<()> {
+        log::debug!("deleting registration of client: {}", self.name);
+
+        Config::locked(self.config.as_deref(), async |config| {
+            let client = config
+                .clients
+                .get(&self.name)
+                .ok_or_else(|| anyhow!("unknown client '{}'", self.name))?;
+            let registration = client
+                .registration
+                .as_ref()
+                .ok_or_else(|| anyhow!("client '{}' was not registered dynamically", self.name))?;
+
+            let http = create_client(&self.http).await?;
+            registration::delete(&http, registration).await?;
+
+            config.clients.remove(&self.name);
+            config
+                .registries
+                .retain(|_, registry| registry.client != self.name);
+            log::info!("deleted client: {}", self.name);
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// The loopback redirect URI, as used by the authorization code flow
+fn redirect_uri(port: u16) -> String {
+    format!("http://localhost:{port}")
+}
+
+/// The scope to register, which needs to include the `openid` scope
+fn registration_scope(scope: &str) -> String {
+    match scope.split(' ').any(|scope| scope == "openid") {
+        true => scope.to_string(),
+        false => format!("openid {scope}"),
+    }
+}
@@ -1,11 +1,41 @@
+#[cfg(unix)]
+use crate::agent;
 use crate::{
-    config::Config,
+    claims::{AccessTokenClaims, print_claims},
+    config::{ClientState, Config},
     http::HttpOptions,
-    oidc::{TokenResult, fetch_token, get_token},
-    utils::inspect::inspect,
+    oidc::get_valid_token,
+    utils::inspect::{inspect, inspect_binding},
 };
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
+use biscuit::{Empty, jws::Compact};
+use serde_json::Value;
 use std::path::PathBuf;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use url::Url;
+
+/// Details of the client a token is bound to
+struct Binding {
+    /// If the client uses DPoP
+    dpop: bool,
+    /// The DPoP proof for the request, if requested
+    proof: Option<String>,
+    /// The thumbprint of the client certificate, if requested
+    thumbprint: Option<String>,
+}
+
+/// Structured output formats
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Output {
+    /// All tokens and their metadata, as JSON
+    Json,
+    /// Shell commands, exporting environment variables
+    Env,
+    /// A header argument for curl
+    Curl,
+    /// A netrc entry, for the host of the URL
+    Netrc,
+}
 
 /// Get a valid token
 #[derive(Debug, clap::Parser)]
@@ -45,46 +75,101 @@ pub struct GetToken {
     #[arg(short, long)]
     pub force: bool,
 
+    /// Refresh the token if it is valid for less than this duration, overrides the default of
+    /// the client
+    #[arg(long)]
+    pub min_valid: Option<humantime::Duration>,
+
+    /// Print the access token together with a DPoP proof for a request, requires a DPoP client
+    #[arg(long, requires = "url", conflicts_with_all = ["id", "refresh", "bearer", "inspect"])]
+    pub dpop: bool,
+
+    /// Print the tokens in a structured format
+    #[arg(short, long, value_enum, conflicts_with_all = ["bearer", "header", "inspect"])]
+    pub output: Option<Output>,
+
+    /// Print the tokens using a template (e.g. '{access_token} {expires}'). Available
+    /// placeholders: token, access_token, id_token, refresh_token, token_type, expires,
+    /// expires_in, scope, dpop
+    #[arg(long, conflicts_with_all = ["bearer", "header", "inspect", "output"])]
+    pub format: Option<String>,
+
+    /// Print only the value of a claim of the token, using a JSON pointer or a dotted path (e.g.
+    /// `realm_access.roles`). Can be repeated. Fails if the claim is absent.
+    #[arg(long, conflicts_with_all = ["bearer", "header", "inspect", "output", "format", "dpop"])]
+    pub claim: Vec<String>,
+
+    /// Print string values of claims without quotes
+    #[arg(long, requires = "claim")]
+    pub raw: bool,
+
+    /// The HTTP method of the request for the DPoP proof
+    #[arg(long, default_value = "GET", requires = "dpop")]
+    pub method: String,
+
+    /// The URL of the request for the DPoP proof, or the netrc entry
+    #[arg(long)]
+    pub url: Option<Url>,
+
+    /// A nonce, provided by the server, to include in the DPoP proof
+    #[arg(long, requires = "dpop")]
+    pub dpop_nonce: Option<String>,
+
     #[command(flatten)]
     pub http: HttpOptions,
 }
 
 impl GetToken {
     pub async fn run(self) -> anyhow::Result<()> {
-        let token = Config::locked(self.config.as_deref(), async |config| {
-            let client = config
-                .by_name_mut(&self.name)
-                .ok_or_else(|| anyhow!("unknown client '{}'", self.name))?;
+        let (state, binding) = match self.token_from_agent().await? {
+            Some(result) => result,
+            None => self.token_from_config().await?,
+        };
 
-            let token = match self.force {
-                true => fetch_token(client, &self.http).await?,
-                false => get_token(client, &self.http).await?,
-            };
+        let token = if self.id {
+            state
+                .id_token
+                .clone()
+                .ok_or_else(|| anyhow!("ID token not available"))?
+        } else if self.refresh {
+            state
+                .refresh_token
+                .clone()
+                .ok_or_else(|| anyhow!("refresh token not available"))?
+        } else {
+            state.access_token.clone()
+        };
 
-            let state = match token {
-                TokenResult::Refreshed(token) => {
-                    log::info!("Got a refreshed token. Storing new state.");
-                    client.state = Some(token.clone());
-                    token
-                }
-                TokenResult::Existing(token) => token,
-            };
+        if !self.claim.is_empty() {
+            return print_claims(&token, &self.claim, self.raw);
+        }
 
-            let token = if self.id {
-                state
-                    .id_token
-                    .ok_or_else(|| anyhow!("ID token not available"))?
-            } else if self.refresh {
-                state
-                    .refresh_token
-                    .ok_or_else(|| anyhow!("refresh token not available"))?
-            } else {
-                state.access_token
+        if self.output.is_some() || self.format.is_some() {
+            let proof = match self.dpop {
+                true => Some(self.proof(&binding)?),
+                false => None,
             };
 
-            Ok(token)
-        })
-        .await?;
+            let tokens = Tokens::new(&state, binding.dpop, proof);
+            return self.print(&token, &tokens);
+        }
+
+        if self.dpop {
+            let proof = self.proof(&binding)?;
+
+            match self.header {
+                true => {
+                    println!("Authorization:DPoP {token}");
+                    println!("DPoP:{proof}");
+                }
+                false => {
+                    println!("Authorization: DPoP {token}");
+                    println!("DPoP: {proof}");
+                }
+            }
+
+            return Ok(());
+        }
 
         match (self.bearer, self.header, self.inspect) {
             (true, _, _) => {
@@ -94,6 +179,9 @@ impl GetToken {
                 println!("Authorization:Bearer {token}");
             }
             (_, _, true) => {
+                if let Some(thumbprint) = &binding.thumbprint {
+                    inspect_binding(&token, thumbprint)?;
+                }
                 inspect(token)?;
             }
             _ => {
@@ -104,3 +192,254 @@ impl GetToken {
         Ok(())
     }
 }
+
+impl GetToken {
+    /// Get the token from the configuration, refreshing it if necessary
+    async fn token_from_config(&self) -> anyhow::Result<(ClientState, Binding)> {
+        Config::locked(self.config.as_deref(), async |config| {
+            let client = config
+                .by_name_mut(&self.name)
+                .ok_or_else(|| anyhow!("unknown client '{}'", self.name))?;
+
+            let min_valid = self.min_valid.map(Into::into);
+            let state = get_valid_token(client, &self.http, min_valid, self.force).await?;
+
+            let proof = match (&client.dpop, &self.url) {
+                (Some(dpop), Some(url)) if self.dpop => Some(dpop.proof(
+                    &self.method,
+                    url,
+                    self.dpop_nonce.as_deref(),
+                    Some(&state.access_token),
+                )?),
+                _ => None,
+            };
+
+            let thumbprint = match self.inspect {
+                true => self
+                    .http
+                    .clone()
+                    .with_certificate(client.r#type.certificate())
+                    .certificate()
+                    .map(|certificate| certificate.thumbprint())
+                    .transpose()?,
+                false => None,
+            };
+
+            let binding = Binding {
+                dpop: client.dpop.is_some(),
+                proof,
+                thumbprint,
+            };
+
+            Ok((state, binding))
+        })
+        .await
+    }
+
+    /// Get the token from the agent, if one is running
+    #[cfg(unix)]
+    async fn token_from_agent(&self) -> anyhow::Result<Option<(ClientState, Binding)>> {
+        let Some(socket) = agent::socket() else {
+            return Ok(None);
+        };
+
+        let stream = match agent::connect(&socket).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::warn!("Unable to use the agent, falling back to the configuration: {err:#}");
+                return Ok(None);
+            }
+        };
+
+        let request = agent::Request {
+            client: self.name.clone(),
+            force: self.force,
+            min_valid: self.min_valid.map(|min_valid| min_valid.as_secs()),
+            // the agent creates the proof, so that the key never leaves it
+            proof: match (self.dpop, &self.url) {
+                (true, Some(url)) => Some(agent::ProofRequest {
+                    method: self.method.clone(),
+                    url: url.clone(),
+                    nonce: self.dpop_nonce.clone(),
+                }),
+                _ => None,
+            },
+            thumbprint: self.inspect,
+        };
+
+        let token = agent::request(stream, &request).await?;
+        let binding = Binding {
+            dpop: token.dpop,
+            proof: token.proof,
+            thumbprint: token.thumbprint,
+        };
+
+        Ok(Some((token.state, binding)))
+    }
+
+    #[cfg(not(unix))]
+    async fn token_from_agent(&self) -> anyhow::Result<Option<(ClientState, Binding)>> {
+        Ok(None)
+    }
+
+    /// The requested DPoP proof
+    fn proof(&self, binding: &Binding) -> anyhow::Result<String> {
+        match (binding.dpop, &binding.proof) {
+            (true, Some(proof)) => Ok(proof.clone()),
+            _ => bail!("Client '{}' does not use DPoP", self.name),
+        }
+    }
+
+    fn print(&self, token: &str, tokens: &Tokens) -> anyhow::Result<()> {
+        if let Some(format) = &self.format {
+            let mut values = match serde_json::to_value(tokens)? {
+                Value::Object(values) => values,
+                _ => Default::default(),
+            };
+            values.insert("token".into(), token.into());
+            println!("{}", render(format, &values)?);
+            return Ok(());
+        }
+
+        match self.output {
+            Some(Output::Json) => println!("{}", serde_json::to_string_pretty(tokens)?),
+            Some(Output::Env) => {
+                let values = [
+                    ("OIDC_ACCESS_TOKEN", Some(tokens.access_token.clone())),
+                    ("OIDC_ID_TOKEN", tokens.id_token.clone()),
+                    ("OIDC_REFRESH_TOKEN", tokens.refresh_token.clone()),
+                    ("OIDC_TOKEN_TYPE", Some(tokens.token_type.to_string())),
+                    (
+                        "OIDC_EXPIRES",
+                        tokens
+                            .expires
+                            .map(|expires| expires.format(&Rfc3339))
+                            .transpose()?,
+                    ),
+                    ("OIDC_DPOP", tokens.dpop.clone()),
+                ];
+                for (name, value) in values {
+                    if let Some(value) = value {
+                        println!("export {name}={}", quote(&value));
+                    }
+                }
+            }
+            Some(Output::Curl) => {
+                print!(
+                    "-H {}",
+                    quote(&format!("Authorization: {} {token}", tokens.token_type))
+                );
+                if let Some(dpop) = &tokens.dpop {
+                    print!(" -H {}", quote(&format!("DPoP: {dpop}")));
+                }
+                println!();
+            }
+            Some(Output::Netrc) => {
+                let Some(host) = self.url.as_ref().and_then(|url| url.host_str()) else {
+                    bail!("netrc output requires a URL, use --url");
+                };
+                println!("machine {host} login oauth2 password {token}");
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// All tokens of a client, with their metadata
+#[derive(Clone, Debug, serde::Serialize)]
+struct Tokens {
+    access_token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    token_type: &'static str,
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    expires: Option<OffsetDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_in: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dpop: Option<String>,
+}
+
+impl Tokens {
+    fn new(state: &ClientState, dpop: bool, proof: Option<String>) -> Self {
+        // the granted scope is only known from the access token
+        let scope = Compact::<AccessTokenClaims, Empty>::new_encoded(&state.access_token)
+            .unverified_payload()
+            .ok()
+            .map(|claims| claims.scope)
+            .filter(|scope| !scope.is_empty());
+
+        Self {
+            access_token: state.access_token.clone(),
+            id_token: state.id_token.clone(),
+            refresh_token: state.refresh_token.clone(),
+            token_type: if dpop { "DPoP" } else { "Bearer" },
+            expires: state.expiration(),
+            expires_in: state
+                .expiration()
+                .map(|expires| (expires - OffsetDateTime::now_utc()).whole_seconds()),
+            scope,
+            dpop: proof,
+        }
+    }
+}
+
+/// Render a template, replacing `{name}` placeholders. Use `{{` and `}}` for literal braces.
+fn render(template: &str, values: &serde_json::Map<String, Value>) -> anyhow::Result<String> {
+    let mut result = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                match values.get(&name) {
+                    Some(Value::String(value)) => result.push_str(value),
+                    Some(Value::Null) | None if is_placeholder(&name) => {}
+                    Some(value) => result.push_str(&value.to_string()),
+                    None => bail!("unknown placeholder: {{{name}}}"),
+                }
+            }
+            c => result.push(c),
+        }
+    }
+
+    Ok(result)
+}
+
+fn is_placeholder(name: &str) -> bool {
+    [
+        "token",
+        "access_token",
+        "id_token",
+        "refresh_token",
+        "token_type",
+        "expires",
+        "expires_in",
+        "scope",
+        "dpop",
+    ]
+    .contains(&name)
+}
+
+/// Quote a value for using it in a POSIX shell
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
@@ -0,0 +1,172 @@
+use crate::{
+    claims::AccessTokenClaims,
+    config::Config,
+    http::{HttpOptions, create_client},
+    jose::{decrypt, verify_remote},
+    oidc::{discover_metadata, endpoint_error, get_valid_token},
+};
+use anyhow::{anyhow, bail};
+use biscuit::{Empty, jws::Compact};
+use colored_json::to_colored_json_auto;
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// Output format of the claims
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Output {
+    /// Colored and indented JSON
+    #[default]
+    Pretty,
+    /// Plain JSON
+    Json,
+}
+
+/// Get the claims of the user from the UserInfo endpoint
+#[derive(Debug, clap::Parser)]
+#[command(rename_all_env = "SNAKE_CASE")]
+pub struct UserInfo {
+    /// Name of the client
+    pub name: String,
+
+    #[arg(from_global)]
+    pub config: Option<PathBuf>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t)]
+    pub output: Output,
+
+    #[command(flatten)]
+    pub http: HttpOptions,
+}
+
+impl UserInfo {
+    pub async fn run(self) -> anyhow::Result<()> {
+        let claims = Config::locked(self.config.as_deref(), async |config| {
+            let client = config
+                .by_name_mut(&self.name)
+                .ok_or_else(|| anyhow!("unknown client '{}'", self.name))?;
+
+            let state = get_valid_token(client, &self.http, None, false).await?;
+
+            let http = self
+                .http
+                .clone()
+                .with_certificate(client.r#type.certificate());
+            let mtls = http.client_certificate.is_some();
+            let http = create_client(&http).await?;
+            let metadata = discover_metadata(&client.issuer_url, &http, mtls).await?;
+
+            let endpoint = metadata
+                .userinfo_endpoint()
+                .ok_or_else(|| anyhow!("issuer has no userinfo endpoint"))?
+                .url();
+
+            let mut request = http.get(endpoint.clone());
+            request = match &client.dpop {
+                Some(dpop) => request
+                    .header(
+                        "Authorization",
+                        format!("DPoP {}", state.access_token),
+                    )
+                    .header(
+                        "DPoP",
+                        dpop.proof("GET", endpoint, None, Some(&state.access_token))?,
+                    ),
+                None => request.bearer_auth(&state.access_token),
+            };
+
+            let response = request.send().await?;
+            if !response.status().is_success() {
+                return Err(endpoint_error("userinfo request", response).await);
+            }
+
+            let jwt = response
+                .headers()
+                .get("Content-Type")
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.starts_with("application/jwt"));
+            let body = response.bytes().await?;
+
+            let claims = match jwt {
+                false => serde_json::from_slice::<Value>(&body)?,
+                true => {
+                    let token = String::from_utf8(body.to_vec())?;
+                    let mut token = token.trim().to_string();
+
+                    let mut claims = None;
+                    if token.split('.').count() == 5 {
+                        let payload = decrypt(&token, client.r#type.client_secret())?;
+                        // the encrypted response may be a signed one, or the claims directly
+                        match serde_json::from_slice::<Value>(&payload) {
+                            Ok(value) => claims = Some(value),
+                            Err(_) => token = String::from_utf8(payload)?,
+                        }
+                    }
+
+                    match claims {
+                        Some(claims) => claims,
+                        None => {
+                            let claims = verify_remote(&http, &token, metadata.jwks_uri().url());
+                            let claims = serde_json::to_value(claims.await?)?;
+                            validate(
+                                &claims,
+                                metadata.issuer().as_str(),
+                                client.r#type.client_id(),
+                            )?;
+                            claims
+                        }
+                    }
+                }
+            };
+
+            if let Some(id_token) = &state.id_token {
+                // the ID token was verified when it was received
+                let expected = Compact::<AccessTokenClaims, Empty>::new_encoded(id_token)
+                    .unverified_payload()?
+                    .sub;
+                let actual = claims["sub"].as_str();
+
+                if actual != Some(expected.as_str()) {
+                    bail!(
+                        "subject of the userinfo response ({}) doesn't match the ID token ({expected})",
+                        actual.unwrap_or("n/a"),
+                    );
+                }
+            }
+
+            Ok(claims)
+        })
+        .await?;
+
+        match self.output {
+            Output::Pretty => println!("{}", to_colored_json_auto(&claims)?),
+            Output::Json => println!("{}", serde_json::to_string(&claims)?),
+        }
+
+        Ok(())
+    }
+}
+
+/// Validate the issuer and audience of a signed response
+fn validate(claims: &Value, issuer: &str, client_id: &str) -> anyhow::Result<()> {
+    let actual = claims["iss"].as_str();
+    if actual != Some(issuer) {
+        bail!(
+            "issuer of the userinfo response ({}) doesn't match the issuer ({issuer})",
+            actual.unwrap_or("n/a"),
+        );
+    }
+
+    let audience = match &claims["aud"] {
+        Value::String(audience) => audience == client_id,
+        Value::Array(audiences) => audiences
+            .iter()
+            .any(|audience| audience.as_str() == Some(client_id)),
+        _ => false,
+    };
+    if !audience {
+        bail!("audience of the userinfo response doesn't contain the client ({client_id})");
+    }
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::{claims::expiration, dpop::DpopKey, http::ClientCertificate, jose::SigningAlgorithm};
 use anyhow::{Context, anyhow};
 use oauth2::TokenResponse;
 use openidconnect::IssuerUrl;
@@ -7,11 +8,16 @@ use std::{
     fs::OpenOptions,
     io::{BufReader, BufWriter, ErrorKind},
     path::{Path, PathBuf},
+    time::Duration,
 };
+use url::Url;
 
 #[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct Config {
     pub clients: BTreeMap<String, Client>,
+    /// Container registries, by host, using a client for authentication
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub registries: BTreeMap<String, Registry>,
 }
 
 impl Config {
@@ -129,19 +135,209 @@ pub struct Client {
     pub r#type: ClientType,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub state: Option<ClientState>,
+    /// Key for binding tokens using DPoP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dpop: Option<DpopKey>,
+    /// Information for managing a dynamically registered client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration: Option<Registration>,
+    /// Minimum remaining lifetime of a token, before it gets refreshed
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "duration")]
+    pub min_valid: Option<Duration>,
+}
+
+impl Client {
+    /// Expire the access token, so that the next request fetches a new one
+    pub fn expire_access_token(&mut self) {
+        // keep the refresh token, which is still required for getting a new token
+        if let Some(state) = &mut self.state {
+            state.expires = Some(time::OffsetDateTime::now_utc());
+        }
+    }
+}
+
+/// (De)serialize an optional duration in a human-readable format (e.g. `30s`)
+mod duration {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.collect_str(&humantime::format_duration(*value)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| humantime::parse_duration(&value).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// A container registry, using the tokens of a client as password
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Registry {
+    /// Name of the client
+    pub client: String,
+    /// The username to provide, together with the token
+    pub username: String,
+}
+
+/// Information for managing a dynamically registered client (RFC 7592)
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Registration {
+    pub registration_access_token: String,
+    pub registration_client_uri: Url,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum ClientType {
     Confidential {
         client_id: String,
-        client_secret: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_secret: Option<String>,
+        #[serde(default, skip_serializing_if = "ClientAuthentication::is_default")]
+        authentication: ClientAuthentication,
+        /// Tokens are issued for a user, which logged in interactively, and can only be refreshed
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        interactive: bool,
     },
     Public {
         client_id: String,
         #[serde(default)]
         client_secret: Option<String>,
     },
+    Password {
+        client_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_secret: Option<String>,
+        username: String,
+        password: PasswordSource,
+    },
+    /// Holds an exchanged token, which can only be refreshed, using the credentials of the client
+    /// it was exchanged by
+    Exchanged {
+        client_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_secret: Option<String>,
+        #[serde(default, skip_serializing_if = "ClientAuthentication::is_default")]
+        authentication: ClientAuthentication,
+    },
+}
+
+impl ClientType {
+    /// The client ID
+    pub fn client_id(&self) -> &str {
+        match self {
+            Self::Confidential { client_id, .. }
+            | Self::Public { client_id, .. }
+            | Self::Password { client_id, .. }
+            | Self::Exchanged { client_id, .. } => client_id,
+        }
+    }
+
+    /// The client certificate, if the client authenticates using mutual TLS
+    pub fn certificate(&self) -> Option<&ClientCertificate> {
+        match self {
+            Self::Confidential {
+                authentication: ClientAuthentication::TlsClientAuth(certificate),
+                ..
+            }
+            | Self::Exchanged {
+                authentication: ClientAuthentication::TlsClientAuth(certificate),
+                ..
+            } => Some(certificate),
+            _ => None,
+        }
+    }
+
+    /// The client secret, if there is one
+    pub fn client_secret(&self) -> Option<&str> {
+        match self {
+            Self::Confidential { client_secret, .. }
+            | Self::Public { client_secret, .. }
+            | Self::Password { client_secret, .. }
+            | Self::Exchanged { client_secret, .. } => client_secret.as_deref(),
+        }
+    }
+
+    /// The type of a client holding a token exchanged by this client
+    pub fn exchanged(&self) -> Self {
+        let authentication = match self {
+            Self::Confidential { authentication, .. } | Self::Exchanged { authentication, .. } => {
+                authentication.clone()
+            }
+            Self::Public { .. } | Self::Password { .. } => ClientAuthentication::ClientSecret,
+        };
+
+        Self::Exchanged {
+            client_id: self.client_id().to_string(),
+            client_secret: self.client_secret().map(ToString::to_string),
+            authentication,
+        }
+    }
+}
+
+/// How a confidential client authenticates with the token endpoint
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ClientAuthentication {
+    /// Send the client secret (`client_secret_basic`)
+    #[default]
+    ClientSecret,
+    /// A JWT, signed with the client secret (`client_secret_jwt`)
+    ClientSecretJwt {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        algorithm: Option<SigningAlgorithm>,
+    },
+    /// A JWT, signed with a private key (`private_key_jwt`)
+    PrivateKeyJwt {
+        key: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key_id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        algorithm: Option<SigningAlgorithm>,
+    },
+    /// Mutual TLS, using a client certificate (`tls_client_auth`, `self_signed_tls_client_auth`)
+    TlsClientAuth(ClientCertificate),
+}
+
+impl ClientAuthentication {
+    pub fn is_default(&self) -> bool {
+        matches!(self, Self::ClientSecret)
+    }
+}
+
+/// Where to get a password from
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub enum PasswordSource {
+    /// The password itself
+    Value(String),
+    /// The name of an environment variable holding the password
+    Env(String),
+    /// A file containing the password
+    File(PathBuf),
+}
+
+impl PasswordSource {
+    /// Get the actual password
+    pub fn resolve(&self) -> anyhow::Result<String> {
+        match self {
+            Self::Value(value) => Ok(value.clone()),
+            Self::Env(name) => std::env::var(name)
+                .with_context(|| format!("reading password from environment variable: {name}")),
+            Self::File(path) => {
+                let password = std::fs::read_to_string(path)
+                    .with_context(|| format!("reading password file: {}", path.display()))?;
+                Ok(password.trim_end_matches(['\r', '\n']).to_string())
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -155,13 +351,22 @@ pub struct ClientState {
     pub expires: Option<time::OffsetDateTime>,
 }
 
+impl ClientState {
+    /// The expiration of the access token, falling back to the `exp` claim of the token
+    pub fn expiration(&self) -> Option<time::OffsetDateTime> {
+        self.expires.or_else(|| expiration(&self.access_token))
+    }
+}
+
 impl From<CoreTokenResponse> for ClientState {
     fn from(token: CoreTokenResponse) -> Self {
         let access_token = token.access_token().clone().into_secret();
         let refresh_token = token.refresh_token().cloned().map(|t| t.into_secret());
+        // without an expiration from the issuer, use the one of the token
         let expires = token
             .expires_in()
-            .map(|exp| time::OffsetDateTime::now_utc() + exp);
+            .map(|exp| time::OffsetDateTime::now_utc() + exp)
+            .or_else(|| expiration(&access_token));
 
         let id_token = token
             .extra_fields()
@@ -0,0 +1,231 @@
+//! Demonstrating Proof of Possession (DPoP, RFC 9449)
+
+use crate::jose::{SigningAlgorithm, SigningKey};
+use anyhow::anyhow;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use biscuit::{
+    ClaimsSet, Empty, RegisteredClaims,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+        EllipticCurveKeyType, JWK,
+    },
+    jws::{RegisteredHeader, Secret},
+};
+use oauth2::{
+    AsyncHttpClient, CsrfToken, HttpClientError, HttpRequest, HttpResponse,
+    http::{HeaderValue, StatusCode},
+};
+use ring::{
+    rand::SystemRandom,
+    signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair},
+};
+use std::{future::Future, pin::Pin, sync::Arc, sync::Mutex};
+use time::OffsetDateTime;
+use url::Url;
+
+const DPOP_HEADER: &str = "DPoP";
+const DPOP_NONCE_HEADER: &str = "DPoP-Nonce";
+
+/// A key for creating DPoP proofs, persisted with the client
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct DpopKey {
+    /// PKCS#8 encoded P-256 private key, base64 URL encoded
+    key: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+struct DpopClaims {
+    htm: String,
+    htu: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ath: Option<String>,
+}
+
+impl DpopKey {
+    /// Generate a new key
+    pub fn generate() -> anyhow::Result<Self> {
+        let pkcs8 =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+                .map_err(|err| anyhow!("failed to generate DPoP key: {err}"))?;
+
+        Ok(Self {
+            key: URL_SAFE_NO_PAD.encode(pkcs8.as_ref()),
+        })
+    }
+
+    fn key_pair(&self) -> anyhow::Result<EcdsaKeyPair> {
+        let pkcs8 = URL_SAFE_NO_PAD.decode(&self.key)?;
+        EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_FIXED_SIGNING,
+            &pkcs8,
+            &SystemRandom::new(),
+        )
+        .map_err(|err| anyhow!("invalid DPoP key: {err}"))
+    }
+
+    /// Create a proof for an HTTP request.
+    ///
+    /// When the proof is used together with an access token, the token must be provided.
+    pub fn proof(
+        &self,
+        method: &str,
+        url: &Url,
+        nonce: Option<&str>,
+        access_token: Option<&str>,
+    ) -> anyhow::Result<String> {
+        let key_pair = self.key_pair()?;
+
+        // the public key is an uncompressed point
+        let public_key = key_pair.public_key().as_ref();
+        let (x, y) = public_key[1..].split_at(32);
+
+        let jwk = JWK {
+            common: CommonParameters::default(),
+            algorithm: AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                key_type: EllipticCurveKeyType::EC,
+                curve: EllipticCurve::P256,
+                x: x.to_vec(),
+                y: y.to_vec(),
+                d: None,
+            }),
+            additional: Empty {},
+        };
+
+        let key = SigningKey {
+            secret: Secret::EcdsaKeyPair(Arc::new(key_pair)),
+            algorithm: SigningAlgorithm::ES256,
+        };
+
+        // the target URI, without query and fragment
+        let mut htu = url.clone();
+        htu.set_query(None);
+        htu.set_fragment(None);
+
+        let claims = ClaimsSet {
+            registered: RegisteredClaims {
+                id: Some(CsrfToken::new_random().into_secret()),
+                issued_at: Some(OffsetDateTime::now_utc().unix_timestamp().into()),
+                ..Default::default()
+            },
+            private: DpopClaims {
+                htm: method.to_uppercase(),
+                htu: htu.to_string(),
+                nonce: nonce.map(ToString::to_string),
+                ath: access_token.map(|token| {
+                    URL_SAFE_NO_PAD.encode(ring::digest::digest(
+                        &ring::digest::SHA256,
+                        token.as_bytes(),
+                    ))
+                }),
+            },
+        };
+
+        let header = RegisteredHeader {
+            media_type: Some("dpop+jwt".into()),
+            web_key: Some(jwk),
+            ..Default::default()
+        };
+
+        key.sign(header, claims)
+    }
+}
+
+/// An HTTP client for token requests, attaching DPoP proofs in case a key is present.
+///
+/// If the server requests the use of a nonce, the request will be retried once, using the
+/// provided nonce.
+pub struct DpopClient {
+    http: reqwest::Client,
+    key: Option<DpopKey>,
+    nonce: Mutex<Option<String>>,
+}
+
+impl DpopClient {
+    pub fn new(http: reqwest::Client, key: Option<DpopKey>) -> Self {
+        Self {
+            http,
+            key,
+            nonce: Default::default(),
+        }
+    }
+
+    async fn send(
+        &self,
+        request: &HttpRequest,
+        key: &DpopKey,
+    ) -> Result<HttpResponse, HttpClientError<reqwest::Error>> {
+        let url = Url::parse(&request.uri().to_string())
+            .map_err(|err| HttpClientError::Other(err.to_string()))?;
+
+        let nonce = self.nonce.lock().ok().and_then(|nonce| nonce.clone());
+
+        let proof = key
+            .proof(request.method().as_str(), &url, nonce.as_deref(), None)
+            .map_err(|err| HttpClientError::Other(err.to_string()))?;
+
+        let mut builder = oauth2::http::Request::builder()
+            .method(request.method().clone())
+            .uri(request.uri().clone());
+        for (name, value) in request.headers() {
+            builder = builder.header(name, value);
+        }
+        let request = builder
+            .header(
+                DPOP_HEADER,
+                HeaderValue::from_str(&proof)
+                    .map_err(|err| HttpClientError::Other(err.to_string()))?,
+            )
+            .body(request.body().clone())?;
+
+        let response = self.http.call(request).await?;
+
+        if let Some(nonce) = response
+            .headers()
+            .get(DPOP_NONCE_HEADER)
+            .and_then(|nonce| nonce.to_str().ok())
+            && let Ok(mut current) = self.nonce.lock()
+        {
+            *current = Some(nonce.to_string());
+        }
+
+        Ok(response)
+    }
+}
+
+/// Check if the server requested using a (new) nonce
+fn use_dpop_nonce(response: &HttpResponse) -> bool {
+    #[derive(serde::Deserialize)]
+    struct ErrorResponse {
+        error: String,
+    }
+
+    response.status() == StatusCode::BAD_REQUEST
+        && response.headers().contains_key(DPOP_NONCE_HEADER)
+        && serde_json::from_slice::<ErrorResponse>(response.body())
+            .is_ok_and(|response| response.error == "use_dpop_nonce")
+}
+
+impl<'c> AsyncHttpClient<'c> for DpopClient {
+    type Error = HttpClientError<reqwest::Error>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<HttpResponse, Self::Error>> + Send + Sync + 'c>>;
+
+    fn call(&'c self, request: HttpRequest) -> Self::Future {
+        Box::pin(async move {
+            let Some(key) = &self.key else {
+                return self.http.call(request).await;
+            };
+
+            let response = self.send(&request, key).await?;
+
+            if use_dpop_nonce(&response) {
+                log::debug!("Server requested DPoP nonce, retrying");
+                return self.send(&request, key).await;
+            }
+
+            Ok(response)
+        })
+    }
+}
@@ -0,0 +1,128 @@
+//! OAuth 2.0 Token Exchange (RFC 8693)
+
+use crate::{
+    claims::expiration,
+    config::ClientState,
+    oidc::{CLIENT_ASSERTION_TYPE, endpoint_error},
+};
+use anyhow::bail;
+use url::Url;
+
+pub const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+
+pub const ACCESS_TOKEN: &str = "urn:ietf:params:oauth:token-type:access_token";
+pub const REFRESH_TOKEN: &str = "urn:ietf:params:oauth:token-type:refresh_token";
+pub const ID_TOKEN: &str = "urn:ietf:params:oauth:token-type:id_token";
+
+/// Parse a token type, either a full URI or one of the short names.
+pub fn parse_token_type(s: &str) -> Result<String, anyhow::Error> {
+    Ok(match s {
+        "access_token" | "access-token" | "access" => ACCESS_TOKEN.into(),
+        "refresh_token" | "refresh-token" | "refresh" => REFRESH_TOKEN.into(),
+        "id_token" | "id-token" | "id" => ID_TOKEN.into(),
+        "jwt" => "urn:ietf:params:oauth:token-type:jwt".into(),
+        "saml1" => "urn:ietf:params:oauth:token-type:saml1".into(),
+        "saml2" => "urn:ietf:params:oauth:token-type:saml2".into(),
+        s if s.contains(':') => s.into(),
+        s => bail!("unknown token type: {s}"),
+    })
+}
+
+/// A token exchange request
+#[derive(Clone, Debug, Default)]
+pub struct ExchangeRequest {
+    pub subject_token: String,
+    pub subject_token_type: String,
+    pub actor_token: Option<(String, String)>,
+    pub audience: Vec<String>,
+    pub resource: Vec<String>,
+    pub scope: Option<String>,
+    pub requested_token_type: Option<String>,
+}
+
+/// A token exchange response
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct ExchangeResponse {
+    pub access_token: String,
+    pub issued_token_type: String,
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+impl From<ExchangeResponse> for ClientState {
+    fn from(response: ExchangeResponse) -> Self {
+        let expires = response
+            .expires_in
+            .map(|exp| time::OffsetDateTime::now_utc() + std::time::Duration::from_secs(exp));
+
+        let (access_token, id_token) = match response.issued_token_type.as_str() {
+            ID_TOKEN => (response.access_token.clone(), Some(response.access_token)),
+            _ => (response.access_token, None),
+        };
+
+        Self {
+            expires: expires.or_else(|| expiration(&access_token)),
+            access_token,
+            id_token,
+            refresh_token: response.refresh_token,
+        }
+    }
+}
+
+/// Perform a token exchange against the token endpoint
+pub async fn exchange(
+    http: &reqwest::Client,
+    token_url: &Url,
+    client_id: &str,
+    client_secret: Option<&str>,
+    assertion: Option<&str>,
+    request: &ExchangeRequest,
+) -> anyhow::Result<ExchangeResponse> {
+    let mut form = vec![
+        ("grant_type", GRANT_TYPE),
+        ("subject_token", &request.subject_token),
+        ("subject_token_type", &request.subject_token_type),
+    ];
+
+    if let Some((actor_token, actor_token_type)) = &request.actor_token {
+        form.push(("actor_token", actor_token));
+        form.push(("actor_token_type", actor_token_type));
+    }
+    for audience in &request.audience {
+        form.push(("audience", audience));
+    }
+    for resource in &request.resource {
+        form.push(("resource", resource));
+    }
+    if let Some(scope) = &request.scope {
+        form.push(("scope", scope));
+    }
+    if let Some(requested_token_type) = &request.requested_token_type {
+        form.push(("requested_token_type", requested_token_type));
+    }
+
+    let mut req = http.post(token_url.clone());
+    match (assertion, client_secret) {
+        (Some(assertion), _) => {
+            form.push(("client_id", client_id));
+            form.push(("client_assertion_type", CLIENT_ASSERTION_TYPE));
+            form.push(("client_assertion", assertion));
+        }
+        (None, Some(client_secret)) => {
+            req = req.basic_auth(client_id, Some(client_secret));
+        }
+        (None, None) => {
+            form.push(("client_id", client_id));
+        }
+    }
+
+    let response = req.form(&form).send().await?;
+
+    if !response.status().is_success() {
+        return Err(endpoint_error("token exchange", response).await);
+    }
+
+    Ok(serde_json::from_slice(&response.bytes().await?)?)
+}
@@ -1,5 +1,7 @@
-use anyhow::Context;
-use reqwest::{header, tls::Version};
+use crate::jose::decode_pem_block;
+use anyhow::{Context, bail};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use reqwest::{Identity, header, tls::Version};
 use std::path::PathBuf;
 
 const USER_AGENT: &str = concat!("OIDC-CLI/", env!("CARGO_PKG_VERSION"));
@@ -58,6 +60,132 @@ pub struct HttpOptions {
     /// Minimum TLS version
     #[arg(long, value_enum, default_value_t = TlsVersion::Tls1_2)]
     pub min_tls_version: TlsVersion,
+
+    /// Client certificate for mutual TLS (PEM, or PKCS#12)
+    #[arg(long)]
+    pub client_certificate: Option<PathBuf>,
+
+    /// Private key of the client certificate (PEM), if not part of the certificate file
+    #[arg(long, requires = "client_certificate")]
+    pub client_key: Option<PathBuf>,
+
+    /// Password of the client certificate (PKCS#12)
+    #[arg(
+        long,
+        env = "OIDC_CLIENT_CERTIFICATE_PASSWORD",
+        requires = "client_certificate"
+    )]
+    pub client_certificate_password: Option<String>,
+}
+
+impl HttpOptions {
+    /// The client certificate from the command line
+    pub fn certificate(&self) -> Option<ClientCertificate> {
+        self.client_certificate
+            .as_ref()
+            .map(|certificate| ClientCertificate {
+                certificate: certificate.clone(),
+                key: self.client_key.clone(),
+                password: self.client_certificate_password.clone(),
+            })
+    }
+
+    /// Use the provided client certificate, unless one was set on the command line
+    pub fn with_certificate(mut self, certificate: Option<&ClientCertificate>) -> Self {
+        if self.client_certificate.is_none()
+            && let Some(certificate) = certificate
+        {
+            self.client_certificate = Some(certificate.certificate.clone());
+            self.client_key = certificate.key.clone();
+            self.client_certificate_password = certificate.password.clone();
+        }
+        self
+    }
+}
+
+/// A client certificate for mutual TLS
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ClientCertificate {
+    /// The certificate, PEM (optionally including the key) or PKCS#12
+    pub certificate: PathBuf,
+    /// The private key (PEM)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<PathBuf>,
+    /// The password of a PKCS#12 file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+impl ClientCertificate {
+    fn read(&self) -> anyhow::Result<Vec<u8>> {
+        std::fs::read(&self.certificate)
+            .with_context(|| format!("Reading client certificate: {}", self.certificate.display()))
+    }
+
+    fn read_key(&self) -> anyhow::Result<Option<Vec<u8>>> {
+        self.key
+            .as_ref()
+            .map(|key| {
+                std::fs::read(key).with_context(|| format!("Reading client key: {}", key.display()))
+            })
+            .transpose()
+    }
+
+    /// Create the identity for the HTTP client
+    pub fn identity(&self) -> anyhow::Result<Identity> {
+        let cert = self.read()?;
+        let key = self.read_key()?;
+
+        if !is_pem(&cert) {
+            return pkcs12_identity(&cert, self.password.as_deref().unwrap_or_default());
+        }
+
+        // when no dedicated key file is present, the certificate file must contain the key
+        let key = key.unwrap_or_else(|| cert.clone());
+        pem_identity(&cert, &key)
+    }
+
+    /// The SHA-256 thumbprint of the certificate (`x5t#S256`)
+    pub fn thumbprint(&self) -> anyhow::Result<String> {
+        let cert = self.read()?;
+        if !is_pem(&cert) {
+            bail!("Evaluating the thumbprint of a PKCS#12 certificate is not supported");
+        }
+
+        let der = decode_pem_block(&String::from_utf8(cert)?, "CERTIFICATE")?;
+        let digest = ring::digest::digest(&ring::digest::SHA256, &der);
+
+        Ok(URL_SAFE_NO_PAD.encode(digest))
+    }
+}
+
+fn is_pem(data: &[u8]) -> bool {
+    data.windows(11).any(|w| w == b"-----BEGIN ")
+}
+
+#[cfg(feature = "native-tls")]
+fn pem_identity(cert: &[u8], key: &[u8]) -> anyhow::Result<Identity> {
+    Ok(Identity::from_pkcs8_pem(cert, key)?)
+}
+
+#[cfg(feature = "native-tls")]
+fn pkcs12_identity(der: &[u8], password: &str) -> anyhow::Result<Identity> {
+    Ok(Identity::from_pkcs12_der(der, password)?)
+}
+
+#[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+fn pem_identity(cert: &[u8], key: &[u8]) -> anyhow::Result<Identity> {
+    let mut pem = cert.to_vec();
+    if key != cert {
+        pem.push(b'\n');
+        pem.extend_from_slice(key);
+    }
+    Ok(Identity::from_pem(&pem)?)
+}
+
+#[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+fn pkcs12_identity(_der: &[u8], _password: &str) -> anyhow::Result<Identity> {
+    bail!("PKCS#12 client certificates require the 'native-tls' feature")
 }
 
 /// A common way to create an HTTP client
@@ -98,6 +226,16 @@ pub async fn create_client(options: &HttpOptions) -> anyhow::Result<reqwest::Cli
         }
     }
 
+    // client certificate
+
+    if let Some(certificate) = options.certificate() {
+        log::info!(
+            "Using client certificate: {}",
+            certificate.certificate.display()
+        );
+        client = client.identity(certificate.identity()?);
+    }
+
     // tls version
 
     client = client.min_tls_version(options.min_tls_version.into());
@@ -0,0 +1,420 @@
+//! Loading keys, signing, verifying and decrypting JWTs
+
+use crate::cache;
+use anyhow::{Context, anyhow, bail};
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+};
+use biscuit::{
+    ClaimsSet, Empty,
+    jwa::{ContentEncryptionAlgorithm, KeyManagementAlgorithm, SignatureAlgorithm},
+    jwe,
+    jwk::{JWK, JWKSet},
+    jws::{Compact, Header, RegisteredHeader, Secret},
+};
+use ring::{
+    rand::SystemRandom,
+    rsa::{KeyPairComponents, PublicKeyComponents},
+    signature::{
+        ECDSA_P256_SHA256_FIXED_SIGNING, ECDSA_P384_SHA384_FIXED_SIGNING, EcdsaKeyPair,
+        EcdsaSigningAlgorithm, RsaKeyPair,
+    },
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{path::Path, sync::Arc};
+use url::Url;
+
+/// Algorithm for signing JWTs
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, clap::ValueEnum,
+)]
+#[value(rename_all = "UPPERCASE")]
+pub enum SigningAlgorithm {
+    HS256,
+    HS384,
+    HS512,
+    RS256,
+    RS384,
+    RS512,
+    PS256,
+    PS384,
+    PS512,
+    ES256,
+    ES384,
+}
+
+impl From<SigningAlgorithm> for SignatureAlgorithm {
+    fn from(value: SigningAlgorithm) -> Self {
+        match value {
+            SigningAlgorithm::HS256 => Self::HS256,
+            SigningAlgorithm::HS384 => Self::HS384,
+            SigningAlgorithm::HS512 => Self::HS512,
+            SigningAlgorithm::RS256 => Self::RS256,
+            SigningAlgorithm::RS384 => Self::RS384,
+            SigningAlgorithm::RS512 => Self::RS512,
+            SigningAlgorithm::PS256 => Self::PS256,
+            SigningAlgorithm::PS384 => Self::PS384,
+            SigningAlgorithm::PS512 => Self::PS512,
+            SigningAlgorithm::ES256 => Self::ES256,
+            SigningAlgorithm::ES384 => Self::ES384,
+        }
+    }
+}
+
+/// A key for signing JWTs
+pub struct SigningKey {
+    pub secret: Secret,
+    pub algorithm: SigningAlgorithm,
+}
+
+impl SigningKey {
+    /// Create an HMAC key from a shared secret
+    pub fn hmac(secret: &str, algorithm: Option<SigningAlgorithm>) -> anyhow::Result<Self> {
+        let algorithm = algorithm.unwrap_or(SigningAlgorithm::HS256);
+        if !matches!(
+            algorithm,
+            SigningAlgorithm::HS256 | SigningAlgorithm::HS384 | SigningAlgorithm::HS512
+        ) {
+            bail!("algorithm {algorithm:?} can't be used with a shared secret");
+        }
+
+        Ok(Self {
+            secret: Secret::bytes_from_str(secret),
+            algorithm,
+        })
+    }
+
+    /// Load a private key from a PEM or JWK file
+    pub fn load(path: &Path, algorithm: Option<SigningAlgorithm>) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("reading private key: {}", path.display()))?;
+
+        let key = match data.trim_start().starts_with('{') {
+            true => Self::from_jwk(&data),
+            false => Self::from_pem(&data),
+        }
+        .with_context(|| format!("loading private key: {}", path.display()))?;
+
+        match algorithm {
+            None => Ok(key),
+            Some(algorithm) => key.with_algorithm(algorithm),
+        }
+    }
+
+    fn with_algorithm(self, algorithm: SigningAlgorithm) -> anyhow::Result<Self> {
+        let compatible = match (&self.secret, self.algorithm) {
+            (Secret::RsaKeyPair(_), _) => matches!(
+                algorithm,
+                SigningAlgorithm::RS256
+                    | SigningAlgorithm::RS384
+                    | SigningAlgorithm::RS512
+                    | SigningAlgorithm::PS256
+                    | SigningAlgorithm::PS384
+                    | SigningAlgorithm::PS512
+            ),
+            (_, current) => current == algorithm,
+        };
+
+        if !compatible {
+            bail!("algorithm {algorithm:?} is not compatible with the private key");
+        }
+
+        Ok(Self {
+            secret: self.secret,
+            algorithm,
+        })
+    }
+
+    fn from_pem(data: &str) -> anyhow::Result<Self> {
+        let (label, der) = decode_pem(data)?;
+
+        match label.as_str() {
+            "RSA PRIVATE KEY" => Ok(Self::rsa(RsaKeyPair::from_der(&der).map_err(rejected)?)),
+            "PRIVATE KEY" => {
+                if let Ok(key) = RsaKeyPair::from_pkcs8(&der) {
+                    return Ok(Self::rsa(key));
+                }
+
+                let rng = SystemRandom::new();
+                for (alg, algorithm) in [
+                    (&ECDSA_P256_SHA256_FIXED_SIGNING, SigningAlgorithm::ES256),
+                    (&ECDSA_P384_SHA384_FIXED_SIGNING, SigningAlgorithm::ES384),
+                ] {
+                    if let Ok(key) = EcdsaKeyPair::from_pkcs8(alg, &der, &rng) {
+                        return Ok(Self::ecdsa(key, algorithm));
+                    }
+                }
+
+                bail!("unsupported private key type")
+            }
+            "EC PRIVATE KEY" => {
+                bail!("SEC1 encoded EC keys are not supported, convert the key to PKCS#8 first")
+            }
+            label => bail!("unsupported PEM type: {label}"),
+        }
+    }
+
+    fn from_jwk(data: &str) -> anyhow::Result<Self> {
+        #[derive(serde::Deserialize)]
+        struct Jwk {
+            kty: String,
+            crv: Option<String>,
+            x: Option<String>,
+            y: Option<String>,
+            n: Option<String>,
+            e: Option<String>,
+            d: Option<String>,
+            p: Option<String>,
+            q: Option<String>,
+            dp: Option<String>,
+            dq: Option<String>,
+            qi: Option<String>,
+        }
+
+        let jwk: Jwk = serde_json::from_str(data)?;
+
+        let param = |name: &str, value: &Option<String>| -> anyhow::Result<Vec<u8>> {
+            let value = value
+                .as_deref()
+                .ok_or_else(|| anyhow!("missing JWK parameter: {name}"))?;
+            Ok(URL_SAFE_NO_PAD.decode(value)?)
+        };
+
+        match jwk.kty.as_str() {
+            "RSA" => {
+                let components = KeyPairComponents {
+                    public_key: PublicKeyComponents {
+                        n: param("n", &jwk.n)?,
+                        e: param("e", &jwk.e)?,
+                    },
+                    d: param("d", &jwk.d)?,
+                    p: param("p", &jwk.p)?,
+                    q: param("q", &jwk.q)?,
+                    dP: param("dp", &jwk.dp)?,
+                    dQ: param("dq", &jwk.dq)?,
+                    qInv: param("qi", &jwk.qi)?,
+                };
+                Ok(Self::rsa(
+                    RsaKeyPair::from_components(&components).map_err(rejected)?,
+                ))
+            }
+            "EC" => {
+                let (alg, algorithm): (&'static EcdsaSigningAlgorithm, _) = match jwk.crv.as_deref()
+                {
+                    Some("P-256") => (&ECDSA_P256_SHA256_FIXED_SIGNING, SigningAlgorithm::ES256),
+                    Some("P-384") => (&ECDSA_P384_SHA384_FIXED_SIGNING, SigningAlgorithm::ES384),
+                    crv => bail!("unsupported curve: {crv:?}"),
+                };
+
+                // uncompressed point
+                let mut public_key = vec![0x04];
+                public_key.extend(param("x", &jwk.x)?);
+                public_key.extend(param("y", &jwk.y)?);
+
+                let key = EcdsaKeyPair::from_private_key_and_public_key(
+                    alg,
+                    &param("d", &jwk.d)?,
+                    &public_key,
+                    &SystemRandom::new(),
+                )
+                .map_err(rejected)?;
+                Ok(Self::ecdsa(key, algorithm))
+            }
+            kty => bail!("unsupported key type: {kty}"),
+        }
+    }
+
+    fn rsa(key: RsaKeyPair) -> Self {
+        Self {
+            secret: Secret::RsaKeyPair(Arc::new(key)),
+            algorithm: SigningAlgorithm::RS256,
+        }
+    }
+
+    fn ecdsa(key: EcdsaKeyPair, algorithm: SigningAlgorithm) -> Self {
+        Self {
+            secret: Secret::EcdsaKeyPair(Arc::new(key)),
+            algorithm,
+        }
+    }
+
+    /// Sign a set of claims, creating a compact JWT
+    pub fn sign<T>(&self, header: RegisteredHeader, claims: ClaimsSet<T>) -> anyhow::Result<String>
+    where
+        T: Serialize + serde::de::DeserializeOwned,
+    {
+        let header = Header {
+            registered: RegisteredHeader {
+                algorithm: self.algorithm.into(),
+                ..header
+            },
+            private: Empty {},
+        };
+
+        let token = Compact::new_decoded(header, claims).into_encoded(&self.secret)?;
+
+        Ok(token.encoded()?.encode())
+    }
+}
+
+/// Fetch a JSON Web Key Set (using the cache), skipping keys which are not supported
+async fn fetch_jwks(http: &reqwest::Client, url: &Url) -> anyhow::Result<JWKSet<Empty>> {
+    parse_jwks(&cache::fetch(http, url).await?, url)
+}
+
+fn parse_jwks(data: &[u8], url: &Url) -> anyhow::Result<JWKSet<Empty>> {
+    #[derive(serde::Deserialize)]
+    struct RawKeySet {
+        keys: Vec<Value>,
+    }
+
+    let jwks: RawKeySet =
+        serde_json::from_slice(data).with_context(|| format!("parsing JWKS: {url}"))?;
+
+    let keys = jwks
+        .keys
+        .into_iter()
+        .filter_map(|key| match serde_json::from_value::<JWK<Empty>>(key) {
+            Ok(key) => Some(key),
+            Err(err) => {
+                log::debug!("Skipping unsupported key: {err}");
+                None
+            }
+        })
+        .collect();
+
+    Ok(JWKSet { keys })
+}
+
+/// Verify the signature of a JWT using the key set at the URL, returning its claims.
+///
+/// If the token was signed with a key missing from the cached key set, the key set is revalidated
+/// once, as the issuer may have rotated its keys.
+pub async fn verify_remote(
+    http: &reqwest::Client,
+    token: &str,
+    url: &Url,
+) -> anyhow::Result<ClaimsSet<Map<String, Value>>> {
+    let jwks = fetch_jwks(http, url).await?;
+    match verify(token, &jwks) {
+        Err(_) if unknown_key(token, &jwks) => {
+            log::debug!("Unknown signing key, revalidating the key set: {url}");
+            let jwks = parse_jwks(&cache::revalidate(http, url).await?, url)?;
+            verify(token, &jwks)
+        }
+        result => result,
+    }
+}
+
+/// Check if the key of a token may be missing from the key set. Without a key ID, any key of the
+/// set may have been used.
+fn unknown_key(token: &str, jwks: &JWKSet<Empty>) -> bool {
+    let token = Compact::<ClaimsSet<Map<String, Value>>, Empty>::new_encoded(token);
+    match token.unverified_header() {
+        Ok(header) => match header.registered.key_id {
+            Some(kid) => jwks.find(&kid).is_none(),
+            None => true,
+        },
+        Err(_) => false,
+    }
+}
+
+/// Verify the signature of a JWT using a key set, returning its claims
+pub fn verify(token: &str, jwks: &JWKSet<Empty>) -> anyhow::Result<ClaimsSet<Map<String, Value>>> {
+    let token = Compact::<ClaimsSet<Map<String, Value>>, Empty>::new_encoded(token);
+    let header = token.unverified_header()?;
+
+    if header.registered.algorithm == SignatureAlgorithm::None {
+        bail!("token is not signed");
+    }
+
+    let token = match header.registered.key_id {
+        Some(_) => token.decode_with_jwks(jwks, Some(header.registered.algorithm)),
+        None => token.decode_with_jwks_ignore_kid(jwks),
+    }
+    .context("failed to verify signature")?;
+
+    Ok(token.payload()?.clone())
+}
+
+/// Decrypt a JWE, returning its payload.
+///
+/// Only symmetric algorithms are supported, using a key derived from the client secret, as
+/// defined by OpenID Connect Core 1.0, section 10.2.
+pub fn decrypt(token: &str, client_secret: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    let token = jwe::Compact::<Vec<u8>, Empty>::new_encrypted(token);
+    let jwe::Compact::Encrypted(encrypted) = &token else {
+        bail!("token is not encrypted");
+    };
+    let header: jwe::Header<Empty> = encrypted.part(0)?;
+    let alg = header.registered.cek_algorithm;
+    let enc = header.registered.enc_algorithm;
+
+    let length = match (alg, enc) {
+        (KeyManagementAlgorithm::A128GCMKW, _)
+        | (KeyManagementAlgorithm::DirectSymmetricKey, ContentEncryptionAlgorithm::A128GCM) => 16,
+        (KeyManagementAlgorithm::A256GCMKW, _)
+        | (KeyManagementAlgorithm::DirectSymmetricKey, ContentEncryptionAlgorithm::A256GCM) => 32,
+        _ => bail!(
+            "unsupported encryption ({alg:?}, {enc:?}), only symmetric algorithms using the client secret are supported"
+        ),
+    };
+
+    let client_secret =
+        client_secret.ok_or_else(|| anyhow!("decrypting the token requires a client secret"))?;
+    // the key is the left-truncated SHA-256 hash of the secret
+    let digest = ring::digest::digest(&ring::digest::SHA256, client_secret.as_bytes());
+    let key = JWK::new_octet_key(&digest.as_ref()[..length], Empty {});
+
+    let token = token
+        .decrypt(&key, alg, enc)
+        .context("failed to decrypt token")?;
+
+    Ok(token.payload()?.clone())
+}
+
+fn rejected(err: ring::error::KeyRejected) -> anyhow::Error {
+    anyhow!("invalid key: {err}")
+}
+
+/// Decode the first PEM block, returning the label and the DER data
+fn decode_pem(data: &str) -> anyhow::Result<(String, Vec<u8>)> {
+    pem_blocks(data)
+        .next()
+        .ok_or_else(|| anyhow!("no PEM block found"))?
+}
+
+/// Decode the first PEM block with the provided label
+pub fn decode_pem_block(data: &str, label: &str) -> anyhow::Result<Vec<u8>> {
+    for block in pem_blocks(data) {
+        let (block_label, der) = block?;
+        if block_label == label {
+            return Ok(der);
+        }
+    }
+
+    bail!("no PEM block of type '{label}' found")
+}
+
+fn pem_blocks(data: &str) -> impl Iterator<Item = anyhow::Result<(String, Vec<u8>)>> {
+    let mut lines = data.lines().map(str::trim);
+
+    std::iter::from_fn(move || {
+        let label = lines.find_map(|line| {
+            line.strip_prefix("-----BEGIN ")
+                .and_then(|line| line.strip_suffix("-----"))
+        })?;
+
+        let end = format!("-----END {label}-----");
+        let body: String = lines.by_ref().take_while(|line| *line != end).collect();
+
+        Some(
+            STANDARD
+                .decode(body)
+                .map(|der| (label.to_string(), der))
+                .map_err(Into::into),
+        )
+    })
+}
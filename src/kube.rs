@@ -0,0 +1,185 @@
+//! Kubernetes client-go credential plugins and kubeconfig files
+
+use anyhow::{Context, bail};
+use serde_yaml::{Mapping, Value};
+use std::{
+    fs::OpenOptions,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+use time::OffsetDateTime;
+
+/// The API version of the `ExecCredential`, as of Kubernetes 1.22
+pub const API_VERSION_V1: &str = "client.authentication.k8s.io/v1";
+/// The beta API version of the `ExecCredential`
+pub const API_VERSION_V1BETA1: &str = "client.authentication.k8s.io/v1beta1";
+
+/// The credential returned to the client
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecCredential {
+    pub api_version: String,
+    pub kind: &'static str,
+    pub status: ExecCredentialStatus,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecCredentialStatus {
+    pub token: String,
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expiration_timestamp: Option<OffsetDateTime>,
+}
+
+impl ExecCredential {
+    pub fn new(
+        api_version: String,
+        token: String,
+        expiration_timestamp: Option<OffsetDateTime>,
+    ) -> Self {
+        Self {
+            api_version,
+            kind: "ExecCredential",
+            status: ExecCredentialStatus {
+                token,
+                expiration_timestamp,
+            },
+        }
+    }
+}
+
+/// The information provided by the client, through the `KUBERNETES_EXEC_INFO` variable
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecInfo {
+    pub api_version: String,
+}
+
+impl ExecInfo {
+    /// Read the information from the environment, if present
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(info) = std::env::var("KUBERNETES_EXEC_INFO") else {
+            return Ok(None);
+        };
+
+        let info: Self =
+            serde_json::from_str(&info).context("unable to parse KUBERNETES_EXEC_INFO")?;
+
+        match info.api_version.as_str() {
+            API_VERSION_V1 | API_VERSION_V1BETA1 => Ok(Some(info)),
+            version => bail!("unsupported ExecCredential API version: {version}"),
+        }
+    }
+}
+
+/// The location of the kubeconfig file, following the rules of `kubectl`
+pub fn default_kubeconfig() -> Option<PathBuf> {
+    // in case of a list of files, kubectl writes new entries to the first one
+    if let Some(path) = std::env::var_os("KUBECONFIG")
+        .as_ref()
+        .and_then(|paths| std::env::split_paths(paths).next())
+        .filter(|path| !path.as_os_str().is_empty())
+    {
+        return Some(path);
+    }
+
+    let base = directories::BaseDirs::new()?;
+    Some(base.home_dir().join(".kube").join("config"))
+}
+
+/// A kubeconfig file, keeping all content which is not managed by us
+pub struct Kubeconfig {
+    content: Mapping,
+}
+
+impl Kubeconfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = match std::fs::read(path) {
+            Ok(data) => serde_yaml::from_slice::<Option<Mapping>>(&data)
+                .with_context(|| format!("unable to parse kubeconfig: {}", path.display()))?
+                .unwrap_or_default(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Mapping::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut config = Self { content };
+        config.set_default("apiVersion", "v1".into());
+        config.set_default("kind", "Config".into());
+
+        Ok(config)
+    }
+
+    pub fn store(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("unable to create parent directory: {}", parent.display())
+            })?;
+        }
+
+        let mut file = OpenOptions::new();
+        file.write(true).create(true).truncate(true);
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            file.mode(0o600);
+        }
+
+        let file = file
+            .open(path)
+            .with_context(|| format!("unable to write kubeconfig: {}", path.display()))?;
+        serde_yaml::to_writer(BufWriter::new(file), &self.content)?;
+
+        Ok(())
+    }
+
+    /// Check if a named entry of a list (e.g. a cluster) exists
+    pub fn contains(&self, list: &str, name: &str) -> bool {
+        self.content
+            .get(list)
+            .and_then(Value::as_sequence)
+            .is_some_and(|entries| entries.iter().any(|entry| entry["name"] == name))
+    }
+
+    /// Insert or replace a named entry of a list (e.g. a user)
+    pub fn upsert(
+        &mut self,
+        list: &str,
+        name: &str,
+        key: &str,
+        value: Value,
+    ) -> anyhow::Result<()> {
+        let entries = self
+            .content
+            .entry(list.into())
+            .or_insert_with(|| Value::Sequence(vec![]));
+        if entries.is_null() {
+            *entries = Value::Sequence(vec![]);
+        }
+        let Some(entries) = entries.as_sequence_mut() else {
+            bail!("invalid kubeconfig, '{list}' is not a list");
+        };
+
+        let mut entry = Mapping::new();
+        entry.insert("name".into(), name.into());
+        entry.insert(key.into(), value);
+
+        match entries.iter_mut().find(|entry| entry["name"] == name) {
+            Some(existing) => *existing = Value::Mapping(entry),
+            None => entries.push(Value::Mapping(entry)),
+        }
+
+        Ok(())
+    }
+
+    pub fn set_current_context(&mut self, context: &str) {
+        self.content
+            .insert("current-context".into(), context.into());
+    }
+
+    fn set_default(&mut self, key: &str, value: Value) {
+        self.content.entry(key.into()).or_insert(value);
+    }
+}
@@ -0,0 +1,111 @@
+//! Ending sessions, using token revocation (RFC 7009) and RP-initiated logout
+
+use crate::{
+    config::{Client, ClientType},
+    oidc::{ExtendedProviderMetadata, authenticate, endpoint_error},
+};
+use anyhow::anyhow;
+use url::Url;
+
+/// Revoke the refresh and access token of a client
+pub async fn revoke(
+    http: &reqwest::Client,
+    metadata: &ExtendedProviderMetadata,
+    client: &Client,
+) -> anyhow::Result<()> {
+    let Some(state) = &client.state else {
+        log::info!("No tokens to revoke");
+        return Ok(());
+    };
+
+    let Some(endpoint) = &metadata.additional_metadata().revocation_endpoint else {
+        log::warn!("Issuer does not support token revocation, tokens stay valid until they expire");
+        return Ok(());
+    };
+
+    let audience = metadata
+        .token_endpoint()
+        .map(|url| url.as_str())
+        .unwrap_or_else(|| metadata.issuer().as_str());
+
+    // revoking the refresh token may revoke the access token too, but that is not guaranteed
+    if let Some(refresh_token) = &state.refresh_token {
+        revoke_token(
+            http,
+            endpoint,
+            &client.r#type,
+            audience,
+            refresh_token,
+            "refresh_token",
+        )
+        .await?;
+        log::info!("Revoked refresh token");
+    }
+
+    revoke_token(
+        http,
+        endpoint,
+        &client.r#type,
+        audience,
+        &state.access_token,
+        "access_token",
+    )
+    .await?;
+    log::info!("Revoked access token");
+
+    Ok(())
+}
+
+async fn revoke_token(
+    http: &reqwest::Client,
+    endpoint: &Url,
+    r#type: &ClientType,
+    audience: &str,
+    token: &str,
+    token_type_hint: &str,
+) -> anyhow::Result<()> {
+    let mut form = vec![
+        ("token", token.to_string()),
+        ("token_type_hint", token_type_hint.to_string()),
+    ];
+
+    let request = authenticate(http.post(endpoint.clone()), &mut form, r#type, audience)?;
+    let response = request.form(&form).send().await?;
+
+    // invalid or already revoked tokens are reported as success too
+    if !response.status().is_success() {
+        return Err(endpoint_error("token revocation", response).await);
+    }
+
+    Ok(())
+}
+
+/// Create the URL for an RP-initiated logout, using the ID token as hint
+pub fn end_session_url(
+    metadata: &ExtendedProviderMetadata,
+    client: &Client,
+    post_logout_redirect_uri: Option<&Url>,
+) -> anyhow::Result<Url> {
+    let mut url = metadata
+        .additional_metadata()
+        .end_session_endpoint
+        .clone()
+        .ok_or_else(|| anyhow!("issuer does not support RP-initiated logout"))?;
+
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("client_id", client.r#type.client_id());
+        if let Some(id_token) = client
+            .state
+            .as_ref()
+            .and_then(|state| state.id_token.as_ref())
+        {
+            query.append_pair("id_token_hint", id_token);
+        }
+        if let Some(uri) = post_logout_redirect_uri {
+            query.append_pair("post_logout_redirect_uri", uri.as_str());
+        }
+    }
+
+    Ok(url)
+}
@@ -1,19 +1,34 @@
 #![deny(clippy::unwrap_used)]
 #![deny(clippy::expect_used)]
 
+#[cfg(unix)]
+mod agent;
+mod cache;
 mod claims;
 mod cmd;
 mod config;
+mod dpop;
+mod exchange;
 mod http;
+mod jose;
+mod kube;
+mod logout;
 mod oidc;
+mod proxy;
+mod registration;
 mod server;
+mod tokens;
 mod utils;
 
 use crate::cmd::Command;
 use clap::Parser;
 use log::LevelFilter;
 use simplelog::{ColorChoice, Config, TermLogger, TerminalMode};
-use std::{path::PathBuf, process::ExitCode};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 #[derive(Debug, clap::Parser)]
 #[command(about, author, version, rename_all_env = "SNAKE_CASE")]
@@ -36,6 +51,10 @@ struct Cli {
     #[arg(short, long, env = "OIDC_CONFIG", global = true)]
     pub config: Option<PathBuf>,
 
+    /// Maximum duration to cache provider metadata and key sets, `0s` revalidates them on every use
+    #[arg(long, env = "OIDC_CACHE_TTL", global = true, default_value = "1h")]
+    pub cache_ttl: humantime::Duration,
+
     #[command(subcommand)]
     pub command: Command,
 }
@@ -62,7 +81,7 @@ fn init_log(cli: &Cli) -> anyhow::Result<()> {
 
 #[tokio::main]
 async fn main() -> ExitCode {
-    let cli = Cli::parse();
+    let cli = Cli::parse_from(args());
     run(cli).await.unwrap_or_else(|err| {
         log::error!("{err}");
         for (n, cause) in err.chain().enumerate().skip(1) {
@@ -72,8 +91,24 @@ async fn main() -> ExitCode {
     })
 }
 
+/// The command line arguments, mapping `docker-credential-oidc` to the `docker-credential` command
+fn args() -> Vec<OsString> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+
+    let docker = args
+        .first()
+        .and_then(|arg0| Path::new(arg0).file_stem())
+        .is_some_and(|name| name == "docker-credential-oidc");
+    if docker {
+        args.insert(1, "docker-credential".into());
+    }
+
+    args
+}
+
 async fn run(cli: Cli) -> anyhow::Result<ExitCode> {
     init_log(&cli)?;
+    cache::init(cli.cache_ttl.into());
     cli.command.run().await
 }
 
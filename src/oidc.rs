@@ -1,48 +1,418 @@
 use crate::{
+    cache,
     claims::RefreshTokenClaims,
-    config::{Client, ClientState, ClientType},
+    config::{Client, ClientAuthentication, ClientState, ClientType},
+    dpop::DpopClient,
     http::{HttpOptions, create_client},
+    jose::SigningKey,
     utils::OrNone,
 };
-use anyhow::{anyhow, bail};
-use biscuit::{Empty, jws::Compact};
-use oauth2::{EndpointMaybeSet, EndpointNotSet, EndpointSet, RefreshToken};
+use anyhow::{Context, anyhow, bail};
+use biscuit::{
+    ClaimsSet, Empty, RegisteredClaims, SingleOrMultiple,
+    jws::{Compact, RegisteredHeader},
+};
+use oauth2::{
+    CsrfToken, EndpointMaybeSet, EndpointNotSet, EndpointSet, RefreshToken, ResourceOwnerPassword,
+    ResourceOwnerUsername,
+};
 use openidconnect::{
-    Audience, ClientId, ClientSecret, Scope,
-    core::{CoreClient, CoreProviderMetadata, CoreTokenResponse},
+    AdditionalProviderMetadata, Audience, ClaimsVerificationError, ClientId, ClientSecret,
+    DeviceAuthorizationUrl, IssuerUrl, Scope, SignatureVerificationError, TokenUrl, UserInfoUrl,
+    core::{
+        CoreAuthDisplay, CoreClaimName, CoreClaimType, CoreClient, CoreClientAuthMethod,
+        CoreGrantType, CoreJsonWebKey, CoreJweContentEncryptionAlgorithm,
+        CoreJweKeyManagementAlgorithm, CoreResponseMode, CoreResponseType,
+        CoreSubjectIdentifierType, CoreTokenResponse,
+    },
 };
+use std::time::Duration;
 use time::OffsetDateTime;
+use url::Url;
+
+/// Provider metadata, which is not part of the OpenID Connect discovery specification
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct ExtendedMetadata {
+    /// RFC 8628 device authorization endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_authorization_endpoint: Option<DeviceAuthorizationUrl>,
+
+    /// RFC 9126 pushed authorization request endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pushed_authorization_request_endpoint: Option<Url>,
+
+    /// RFC 9126 requirement to use pushed authorization requests
+    #[serde(default)]
+    pub require_pushed_authorization_requests: bool,
+
+    /// RFC 7009 token revocation endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation_endpoint: Option<Url>,
+
+    /// RFC 7662 token introspection endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub introspection_endpoint: Option<Url>,
+
+    /// OpenID Connect RP-initiated logout endpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_session_endpoint: Option<Url>,
+
+    /// RFC 8705 endpoints to use with mutual TLS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtls_endpoint_aliases: Option<MtlsEndpointAliases>,
+}
+
+/// Alternative endpoints, for use with mutual TLS (RFC 8705)
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct MtlsEndpointAliases {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_endpoint: Option<TokenUrl>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub userinfo_endpoint: Option<UserInfoUrl>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_authorization_endpoint: Option<DeviceAuthorizationUrl>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pushed_authorization_request_endpoint: Option<Url>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation_endpoint: Option<Url>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub introspection_endpoint: Option<Url>,
+}
+
+impl AdditionalProviderMetadata for ExtendedMetadata {}
+
+/// Provider metadata, including [`ExtendedMetadata`]
+pub type ExtendedProviderMetadata = openidconnect::ProviderMetadata<
+    ExtendedMetadata,
+    CoreAuthDisplay,
+    CoreClientAuthMethod,
+    CoreClaimName,
+    CoreClaimType,
+    CoreGrantType,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJweKeyManagementAlgorithm,
+    CoreJsonWebKey,
+    CoreResponseMode,
+    CoreResponseType,
+    CoreSubjectIdentifierType,
+>;
+
+pub const CLIENT_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// Lifetime of a client assertion, in seconds
+const ASSERTION_LIFETIME: i64 = 60;
+
+/// Discover the provider metadata, including its key set.
+///
+/// The metadata and key set are cached, see [`crate::cache`]. When using mutual TLS, the endpoint
+/// aliases announced by the provider replace the original endpoints.
+pub async fn discover(
+    issuer_url: &IssuerUrl,
+    http: &reqwest::Client,
+    mtls: bool,
+) -> anyhow::Result<ExtendedProviderMetadata> {
+    let metadata = discover_metadata(issuer_url, http, mtls).await?;
+    let jwks = cache::fetch(http, metadata.jwks_uri().url()).await?;
+    set_jwks(metadata, &jwks)
+}
+
+/// Discover the provider metadata, revalidating its cached key set.
+///
+/// Used when a token was signed with a key missing from the key set, as the provider may have
+/// rotated its keys.
+pub async fn rediscover(
+    issuer_url: &IssuerUrl,
+    http: &reqwest::Client,
+    mtls: bool,
+) -> anyhow::Result<ExtendedProviderMetadata> {
+    let metadata = discover_metadata(issuer_url, http, mtls).await?;
+    let jwks = cache::revalidate(http, metadata.jwks_uri().url()).await?;
+    set_jwks(metadata, &jwks)
+}
+
+fn set_jwks(
+    metadata: ExtendedProviderMetadata,
+    jwks: &[u8],
+) -> anyhow::Result<ExtendedProviderMetadata> {
+    let jwks = serde_json::from_slice(jwks)
+        .with_context(|| format!("parsing JWKS: {}", metadata.jwks_uri().as_str()))?;
+
+    Ok(metadata.set_jwks(jwks))
+}
+
+/// Check if verifying an ID token failed, because it was signed with a key missing from the key set
+pub fn unknown_key(err: &ClaimsVerificationError) -> bool {
+    matches!(
+        err,
+        ClaimsVerificationError::SignatureVerification(SignatureVerificationError::NoMatchingKey)
+    )
+}
+
+/// Discover the provider metadata, without loading its key set.
+pub async fn discover_metadata(
+    issuer_url: &IssuerUrl,
+    http: &reqwest::Client,
+    mtls: bool,
+) -> anyhow::Result<ExtendedProviderMetadata> {
+    let url = issuer_url.join(".well-known/openid-configuration")?;
+    let mut metadata: ExtendedProviderMetadata =
+        serde_json::from_slice(&cache::fetch(http, &url).await?)
+            .with_context(|| format!("parsing provider metadata: {url}"))?;
+    if metadata.issuer() != issuer_url {
+        bail!(
+            "unexpected issuer URI `{}` (expected `{}`)",
+            metadata.issuer().as_str(),
+            issuer_url.as_str()
+        );
+    }
+
+    if mtls && let Some(aliases) = metadata.additional_metadata().mtls_endpoint_aliases.clone() {
+        log::debug!("Using mTLS endpoint aliases");
+
+        if let Some(token_endpoint) = aliases.token_endpoint {
+            metadata = metadata.set_token_endpoint(Some(token_endpoint));
+        }
+        if let Some(userinfo_endpoint) = aliases.userinfo_endpoint {
+            metadata = metadata.set_userinfo_endpoint(Some(userinfo_endpoint));
+        }
+        if let Some(device_authorization_endpoint) = aliases.device_authorization_endpoint {
+            metadata
+                .additional_metadata_mut()
+                .device_authorization_endpoint = Some(device_authorization_endpoint);
+        }
+        if let Some(par_endpoint) = aliases.pushed_authorization_request_endpoint {
+            metadata
+                .additional_metadata_mut()
+                .pushed_authorization_request_endpoint = Some(par_endpoint);
+        }
+        if let Some(revocation_endpoint) = aliases.revocation_endpoint {
+            metadata.additional_metadata_mut().revocation_endpoint = Some(revocation_endpoint);
+        }
+        if let Some(introspection_endpoint) = aliases.introspection_endpoint {
+            metadata.additional_metadata_mut().introspection_endpoint =
+                Some(introspection_endpoint);
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// Push an authorization request (RFC 9126).
+///
+/// Takes the parameters of the full authorization URL, pushes them to the endpoint, and returns
+/// the URL to open in the browser instead, only containing the client ID and the request URI. The
+/// request is authenticated the same way as requests to the token endpoint. For mutual TLS, the
+/// HTTP client must carry the client certificate.
+pub async fn push_authorization_request(
+    http: &reqwest::Client,
+    endpoint: &Url,
+    authorize_url: &Url,
+    r#type: &ClientType,
+) -> anyhow::Result<Url> {
+    #[derive(serde::Deserialize)]
+    struct PushResponse {
+        request_uri: String,
+    }
+
+    let mut form: Vec<(String, String)> = authorize_url
+        .query_pairs()
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+
+    let mut auth = vec![];
+    let request = authenticate(
+        http.post(endpoint.clone()),
+        &mut auth,
+        r#type,
+        endpoint.as_str(),
+    )?;
+    // the client ID is already part of the authorization request
+    form.extend(
+        auth.into_iter()
+            .filter(|(name, _)| *name != "client_id")
+            .map(|(name, value)| (name.to_string(), value)),
+    );
+
+    let request = request.form(&form);
+
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(endpoint_error("pushed authorization request", response).await);
+    }
+
+    let response: PushResponse = serde_json::from_slice(&response.bytes().await?)?;
+
+    let mut url = authorize_url.clone();
+    url.query_pairs_mut()
+        .clear()
+        .append_pair("client_id", r#type.client_id())
+        .append_pair("request_uri", &response.request_uri);
+
+    Ok(url)
+}
+
+/// Authenticate a request to an endpoint of the issuer, using the credentials of the client.
+///
+/// Depending on the authentication method, this either adds a basic authorization header or
+/// adds the client ID (and a client assertion) to the form.
+pub fn authenticate(
+    request: reqwest::RequestBuilder,
+    form: &mut Vec<(&'static str, String)>,
+    r#type: &ClientType,
+    audience: &str,
+) -> anyhow::Result<reqwest::RequestBuilder> {
+    let client_id = r#type.client_id();
+
+    if let Some(assertion) = client_assertion(r#type, audience)? {
+        form.push(("client_id", client_id.to_string()));
+        form.push(("client_assertion_type", CLIENT_ASSERTION_TYPE.to_string()));
+        form.push(("client_assertion", assertion));
+        return Ok(request);
+    }
+
+    Ok(match r#type.client_secret() {
+        Some(client_secret) => request.basic_auth(client_id, Some(client_secret)),
+        None => {
+            form.push(("client_id", client_id.to_string()));
+            request
+        }
+    })
+}
+
+/// Create an error from a failed response of an OAuth2 endpoint
+pub async fn endpoint_error(operation: &str, response: reqwest::Response) -> anyhow::Error {
+    #[derive(serde::Deserialize)]
+    struct ErrorResponse {
+        error: String,
+        #[serde(default)]
+        error_description: Option<String>,
+    }
+
+    let status = response.status();
+    let body = match response.text().await {
+        Ok(body) => body,
+        Err(err) => return anyhow!("{operation} failed ({status}): {err}"),
+    };
+
+    match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(ErrorResponse {
+            error,
+            error_description: Some(description),
+        }) => anyhow!("{operation} failed ({status}): {error}: {description}"),
+        Ok(ErrorResponse { error, .. }) => anyhow!("{operation} failed ({status}): {error}"),
+        Err(_) => anyhow!("{operation} failed ({status}): {body}"),
+    }
+}
 
 pub enum TokenResult {
     Existing(ClientState),
     Refreshed(ClientState),
 }
 
+impl TokenResult {
+    /// Store a refreshed token as the new state, returning the current state
+    pub fn store(self, state: &mut Option<ClientState>) -> ClientState {
+        match self {
+            Self::Refreshed(token) => {
+                log::info!("Got a refreshed token. Storing new state.");
+                *state = Some(token.clone());
+                token
+            }
+            Self::Existing(token) => token,
+        }
+    }
+}
+
 /// Fetch a new token
 pub async fn fetch_token(config: &Client, http: &HttpOptions) -> anyhow::Result<TokenResult> {
     log::debug!("Fetching new token");
 
-    let http = create_client(http).await?;
+    let http = http.clone().with_certificate(config.r#type.certificate());
+    let mtls = http.client_certificate.is_some();
+    let discovery = create_client(&http).await?;
+    let http = DpopClient::new(discovery.clone(), config.dpop.clone());
 
     match &config.r#type {
         ClientType::Confidential {
             client_id,
             client_secret,
+            authentication,
+            ..
+        }
+        | ClientType::Exchanged {
+            client_id,
+            client_secret,
+            authentication,
         } => {
-            let provider_metadata =
-                CoreProviderMetadata::discover_async(config.issuer_url.clone(), &http).await?;
+            // tokens of a user, or exchanged ones, can only be refreshed, as the client
+            // credentials grant would issue tokens for the client itself
+            let refresh_only = matches!(
+                config.r#type,
+                ClientType::Confidential {
+                    interactive: true,
+                    ..
+                } | ClientType::Exchanged { .. }
+            );
+
+            let provider_metadata = discover(&config.issuer_url, &discovery, mtls).await?;
+
+            let token_url = provider_metadata
+                .token_endpoint()
+                .map(|url| url.to_string())
+                .ok_or_else(|| anyhow!("issuer has no token endpoint"))?;
+
+            // with an assertion, we must not send the secret
+            let client_secret = match authentication {
+                ClientAuthentication::ClientSecret => client_secret.clone().map(ClientSecret::new),
+                _ => None,
+            };
 
             let client = CoreClient::from_provider_metadata(
                 provider_metadata,
                 ClientId::new(client_id.clone()),
-                Some(ClientSecret::new(client_secret.clone())),
+                client_secret,
             );
 
-            let token = client
+            if let Some(refresh_token) = config
+                .state
+                .as_ref()
+                .and_then(|state| state.refresh_token.clone())
+            {
+                let assertion = client_assertion(&config.r#type, &token_url)?;
+                match refresh_token_request(
+                    &http,
+                    &client,
+                    config.scope.as_deref(),
+                    assertion,
+                    refresh_token,
+                )
+                .await
+                {
+                    Ok(token) => return Ok(TokenResult::Refreshed(token.into())),
+                    Err(err) if refresh_only => return Err(err),
+                    Err(err) => {
+                        log::info!("Failed to refresh token, requesting a new one: {err}");
+                    }
+                }
+            }
+
+            if refresh_only {
+                bail!(
+                    "Expired token, without having a refresh token. You will need to re-login or exchange a new one."
+                );
+            }
+
+            let mut request = client
                 .exchange_client_credentials()?
-                .add_scopes(extra_scopes(config.scope.as_deref()))
-                .request_async(&http)
-                .await?;
+                .add_scopes(extra_scopes(config.scope.as_deref()));
+
+            if let Some(assertion) = client_assertion(&config.r#type, &token_url)? {
+                request = request
+                    .add_extra_param("client_assertion_type", CLIENT_ASSERTION_TYPE)
+                    .add_extra_param("client_assertion", assertion);
+            }
+
+            let token = request.request_async(&http).await?;
 
             Ok(TokenResult::Refreshed(token.into()))
         }
@@ -56,8 +426,7 @@ pub async fn fetch_token(config: &Client, http: &HttpOptions) -> anyhow::Result<
                 );
             };
 
-            let provider_metadata =
-                CoreProviderMetadata::discover_async(config.issuer_url.clone(), &http).await?;
+            let provider_metadata = discover(&config.issuer_url, &discovery, mtls).await?;
 
             let refresh_token = state.refresh_token.clone().ok_or_else(|| anyhow!("Expired token of a public client, without having a refresh token. You will need to re-login."))?;
 
@@ -68,20 +437,85 @@ pub async fn fetch_token(config: &Client, http: &HttpOptions) -> anyhow::Result<
             );
 
             let token =
-                refresh_token_request(&http, &client, config.scope.as_deref(), refresh_token)
+                refresh_token_request(&http, &client, config.scope.as_deref(), None, refresh_token)
                     .await?;
 
             Ok(TokenResult::Refreshed(token.into()))
         }
+        ClientType::Password {
+            client_id,
+            client_secret,
+            username,
+            password,
+        } => {
+            let provider_metadata = discover(&config.issuer_url, &discovery, mtls).await?;
+
+            let client = CoreClient::from_provider_metadata(
+                provider_metadata,
+                ClientId::new(client_id.clone()),
+                client_secret.clone().map(ClientSecret::new),
+            );
+
+            if let Some(refresh_token) = config
+                .state
+                .as_ref()
+                .and_then(|state| state.refresh_token.clone())
+            {
+                match refresh_token_request(
+                    &http,
+                    &client,
+                    config.scope.as_deref(),
+                    None,
+                    refresh_token,
+                )
+                .await
+                {
+                    Ok(token) => return Ok(TokenResult::Refreshed(token.into())),
+                    Err(err) => {
+                        log::info!("Failed to refresh token, requesting a new one: {err}");
+                    }
+                }
+            }
+
+            let password = password.resolve()?;
+
+            let token = client
+                .exchange_password(
+                    &ResourceOwnerUsername::new(username.clone()),
+                    &ResourceOwnerPassword::new(password),
+                )?
+                .add_scopes(extra_scopes(config.scope.as_deref()))
+                .request_async(&http)
+                .await?;
+
+            Ok(TokenResult::Refreshed(token.into()))
+        }
     }
 }
 
 /// Get the current token, or fetch a new one
 pub async fn get_token(config: &Client, http: &HttpOptions) -> anyhow::Result<TokenResult> {
+    get_token_valid_for(config, http, None).await
+}
+
+/// Get a token which is still valid for at least `min_valid`, or the default of the client
+pub async fn get_token_valid_for(
+    config: &Client,
+    http: &HttpOptions,
+    min_valid: Option<Duration>,
+) -> anyhow::Result<TokenResult> {
     if let Some(state) = &config.state {
-        log::debug!("Token expires: {}", OrNone(&state.expires));
-        if let Some(expires) = state.expires
-            && expires > OffsetDateTime::now_utc()
+        // states stored without an expiration may still carry one in the token
+        let expires = state.expiration();
+        log::debug!("Token expires: {}", OrNone(&expires));
+
+        let min_valid = min_valid.or(config.min_valid).unwrap_or_default();
+        let min_valid = time::Duration::try_from(min_valid).unwrap_or(time::Duration::MAX);
+
+        if let Some(expires) = expires
+            && expires
+                .checked_sub(min_valid)
+                .is_some_and(|expires| expires > OffsetDateTime::now_utc())
         {
             return Ok(TokenResult::Existing(state.clone()));
         }
@@ -90,6 +524,22 @@ pub async fn get_token(config: &Client, http: &HttpOptions) -> anyhow::Result<To
     fetch_token(config, http).await
 }
 
+/// Get a token which is still valid for at least `min_valid`, or a new one if `force` is set,
+/// storing a refreshed token as the state of the client
+pub async fn get_valid_token(
+    client: &mut Client,
+    http: &HttpOptions,
+    min_valid: Option<Duration>,
+    force: bool,
+) -> anyhow::Result<ClientState> {
+    let token = match force {
+        true => fetch_token(client, http).await?,
+        false => get_token_valid_for(client, http, min_valid).await?,
+    };
+
+    Ok(token.store(&mut client.state))
+}
+
 pub fn extra_scopes(scope: Option<&str>) -> impl Iterator<Item = Scope> {
     scope
         .into_iter()
@@ -128,7 +578,7 @@ pub fn check_refresh_token_expiration(refresh_token: &str) -> anyhow::Result<()>
 }
 
 pub async fn refresh_token_request(
-    http: &reqwest::Client,
+    http: &DpopClient,
     client: &CoreClient<
         EndpointSet,
         EndpointNotSet,
@@ -138,13 +588,78 @@ pub async fn refresh_token_request(
         EndpointMaybeSet,
     >,
     scope: Option<&str>,
+    assertion: Option<String>,
     refresh_token: String,
 ) -> anyhow::Result<CoreTokenResponse> {
     check_refresh_token_expiration(&refresh_token)?;
 
-    Ok(client
-        .exchange_refresh_token(&RefreshToken::new(refresh_token))?
-        .add_scopes(extra_scopes(scope))
-        .request_async(http)
-        .await?)
+    let refresh_token = RefreshToken::new(refresh_token);
+    let mut request = client
+        .exchange_refresh_token(&refresh_token)?
+        .add_scopes(extra_scopes(scope));
+
+    if let Some(assertion) = assertion {
+        request = request
+            .add_extra_param("client_assertion_type", CLIENT_ASSERTION_TYPE)
+            .add_extra_param("client_assertion", assertion);
+    }
+
+    Ok(request.request_async(http).await?)
+}
+
+/// Create a client assertion (RFC 7523), in case the client is configured to use one.
+pub fn client_assertion(r#type: &ClientType, audience: &str) -> anyhow::Result<Option<String>> {
+    let (ClientType::Confidential {
+        client_id,
+        client_secret,
+        authentication,
+        ..
+    }
+    | ClientType::Exchanged {
+        client_id,
+        client_secret,
+        authentication,
+    }) = r#type
+    else {
+        return Ok(None);
+    };
+
+    let (key, key_id) = match authentication {
+        ClientAuthentication::ClientSecret | ClientAuthentication::TlsClientAuth(_) => {
+            return Ok(None);
+        }
+        ClientAuthentication::ClientSecretJwt { algorithm } => {
+            let client_secret = client_secret
+                .as_deref()
+                .ok_or_else(|| anyhow!("client_secret_jwt requires a client secret"))?;
+            (SigningKey::hmac(client_secret, *algorithm)?, None)
+        }
+        ClientAuthentication::PrivateKeyJwt {
+            key,
+            key_id,
+            algorithm,
+        } => (SigningKey::load(key, *algorithm)?, key_id.clone()),
+    };
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    let claims = ClaimsSet {
+        registered: RegisteredClaims {
+            issuer: Some(client_id.clone()),
+            subject: Some(client_id.clone()),
+            audience: Some(SingleOrMultiple::Single(audience.to_string())),
+            expiry: Some((now + ASSERTION_LIFETIME).into()),
+            issued_at: Some(now.into()),
+            id: Some(CsrfToken::new_random().into_secret()),
+            ..Default::default()
+        },
+        private: Empty {},
+    };
+
+    let header = RegisteredHeader {
+        key_id,
+        ..Default::default()
+    };
+
+    Ok(Some(key.sign(header, claims)?))
 }
@@ -0,0 +1,148 @@
+//! An authenticating reverse proxy
+
+use crate::tokens::TokenSource;
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, http::StatusCode, web};
+use std::net::SocketAddr;
+use url::Url;
+
+/// Maximum size of a request body
+const MAX_BODY_SIZE: usize = 100 * 1024 * 1024;
+
+/// Headers which only apply to a single connection, and must not be forwarded
+const HOP_BY_HOP: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+];
+
+struct Proxy {
+    upstream: Url,
+    http: reqwest::Client,
+    tokens: TokenSource,
+}
+
+impl Proxy {
+    /// The upstream URL for a request
+    fn url(&self, request: &HttpRequest) -> Url {
+        let mut url = self.upstream.clone();
+
+        let path = format!(
+            "{}{}",
+            self.upstream.path().trim_end_matches('/'),
+            request.path()
+        );
+        url.set_path(&path);
+        url.set_query(Some(request.query_string()).filter(|query| !query.is_empty()));
+
+        url
+    }
+
+    async fn send(
+        &self,
+        request: &HttpRequest,
+        body: &web::Bytes,
+        token: &str,
+    ) -> anyhow::Result<reqwest::Response> {
+        let url = self.url(request);
+        let method = reqwest::Method::from_bytes(request.method().as_str().as_bytes())?;
+
+        let mut upstream = self.http.request(method, url.clone());
+        for (name, value) in request.headers() {
+            if forward(name) && name != "authorization" && name != "dpop" {
+                upstream = upstream.header(name.as_str(), value.as_bytes());
+            }
+        }
+
+        let (authorization, proof) =
+            self.tokens
+                .authorization(token, request.method().as_str(), &url)?;
+        upstream = upstream.header("Authorization", authorization);
+        if let Some(proof) = proof {
+            upstream = upstream.header("DPoP", proof);
+        }
+
+        Ok(upstream.body(body.clone()).send().await?)
+    }
+
+    async fn handle(
+        &self,
+        request: &HttpRequest,
+        body: &web::Bytes,
+    ) -> anyhow::Result<HttpResponse> {
+        let token = self.tokens.token().await;
+        let mut response = self.send(request, body, &token).await?;
+
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            self.tokens.renew(&token).await?;
+            let token = self.tokens.token().await;
+            response = self.send(request, body, &token).await?;
+        }
+
+        log::info!(
+            "{} {} -> {}",
+            request.method(),
+            request.path(),
+            response.status()
+        );
+
+        let mut result = HttpResponse::build(StatusCode::from_u16(response.status().as_u16())?);
+        for (name, value) in response.headers() {
+            if forward(name.as_str()) && name != "content-length" {
+                result.append_header((name.as_str(), value.as_bytes()));
+            }
+        }
+
+        Ok(result.body(response.bytes().await?))
+    }
+}
+
+fn forward(name: impl AsRef<str>) -> bool {
+    !HOP_BY_HOP.contains(&name.as_ref())
+}
+
+async fn proxy(request: HttpRequest, body: web::Bytes, proxy: web::Data<Proxy>) -> HttpResponse {
+    match proxy.handle(&request, &body).await {
+        Ok(response) => response,
+        Err(err) => {
+            log::warn!("Failed to forward request: {err}");
+            HttpResponse::BadGateway().body(err.to_string())
+        }
+    }
+}
+
+/// Run the proxy, until it gets stopped
+pub async fn run(
+    listen: SocketAddr,
+    upstream: Url,
+    http: reqwest::Client,
+    tokens: TokenSource,
+) -> anyhow::Result<()> {
+    let proxy = web::Data::new(Proxy {
+        upstream,
+        http,
+        tokens,
+    });
+
+    let refresh = proxy.clone();
+    let refresh = tokio::spawn(async move { refresh.tokens.keep_valid().await });
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(proxy.clone())
+            .app_data(web::PayloadConfig::new(MAX_BODY_SIZE))
+            .default_service(web::to(self::proxy))
+    })
+    .bind(listen)?;
+
+    log::info!("Listening on: http://{listen}");
+    server.run().await?;
+    refresh.abort();
+
+    Ok(())
+}
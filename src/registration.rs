@@ -0,0 +1,157 @@
+//! Dynamic client registration (RFC 7591) and management (RFC 7592)
+
+use crate::{config::Registration, oidc::endpoint_error};
+use serde_json::{Map, Value};
+use url::Url;
+
+/// Metadata of a client to register
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct ClientMetadata {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub redirect_uris: Vec<String>,
+    pub grant_types: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub response_types: Vec<String>,
+    pub token_endpoint_auth_method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub application_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+impl ClientMetadata {
+    /// A native, public client using the authorization code flow with a loopback redirect URI
+    pub fn public(redirect_uri: String) -> Self {
+        Self {
+            redirect_uris: vec![redirect_uri],
+            grant_types: vec!["authorization_code".into(), "refresh_token".into()],
+            response_types: vec!["code".into()],
+            token_endpoint_auth_method: "none".into(),
+            application_type: Some("native".into()),
+            ..Default::default()
+        }
+    }
+
+    /// A confidential client, using the client credentials grant
+    pub fn confidential() -> Self {
+        Self {
+            grant_types: vec!["client_credentials".into()],
+            token_endpoint_auth_method: "client_secret_basic".into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The response of the issuer, describing the registered client
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct RegistrationResponse {
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    #[serde(default)]
+    pub registration_access_token: Option<String>,
+    #[serde(default)]
+    pub registration_client_uri: Option<Url>,
+}
+
+impl RegistrationResponse {
+    /// The information for managing the client, if the issuer supports it
+    pub fn registration(&self) -> Option<Registration> {
+        match (
+            &self.registration_access_token,
+            &self.registration_client_uri,
+        ) {
+            (Some(token), Some(uri)) => Some(Registration {
+                registration_access_token: token.clone(),
+                registration_client_uri: uri.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Register a new client
+pub async fn register(
+    http: &reqwest::Client,
+    endpoint: &Url,
+    initial_access_token: Option<&str>,
+    metadata: &ClientMetadata,
+) -> anyhow::Result<RegistrationResponse> {
+    let mut request = http
+        .post(endpoint.clone())
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_vec(metadata)?);
+    if let Some(token) = initial_access_token {
+        request = request.bearer_auth(token);
+    }
+
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(endpoint_error("client registration", response).await);
+    }
+
+    Ok(serde_json::from_slice(&response.bytes().await?)?)
+}
+
+/// Read the current metadata of a registered client
+pub async fn read(
+    http: &reqwest::Client,
+    registration: &Registration,
+) -> anyhow::Result<Map<String, Value>> {
+    let response = http
+        .get(registration.registration_client_uri.clone())
+        .bearer_auth(&registration.registration_access_token)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(endpoint_error("reading client registration", response).await);
+    }
+
+    Ok(serde_json::from_slice(&response.bytes().await?)?)
+}
+
+/// Update a registered client, replacing its metadata
+pub async fn update(
+    http: &reqwest::Client,
+    registration: &Registration,
+    mut metadata: Map<String, Value>,
+) -> anyhow::Result<RegistrationResponse> {
+    // these must not be part of an update request
+    for field in [
+        "registration_access_token",
+        "registration_client_uri",
+        "client_secret_expires_at",
+        "client_id_issued_at",
+    ] {
+        metadata.remove(field);
+    }
+
+    let response = http
+        .put(registration.registration_client_uri.clone())
+        .bearer_auth(&registration.registration_access_token)
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_vec(&metadata)?)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(endpoint_error("updating client registration", response).await);
+    }
+
+    Ok(serde_json::from_slice(&response.bytes().await?)?)
+}
+
+/// Delete a registered client
+pub async fn delete(http: &reqwest::Client, registration: &Registration) -> anyhow::Result<()> {
+    let response = http
+        .delete(registration.registration_client_uri.clone())
+        .bearer_auth(&registration.registration_access_token)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(endpoint_error("deleting client registration", response).await);
+    }
+
+    Ok(())
+}
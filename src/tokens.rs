@@ -0,0 +1,197 @@
+//! Providing valid tokens to long-running operations
+
+use crate::{
+    config::{Client, ClientState, Config},
+    dpop::DpopKey,
+    http::HttpOptions,
+    oidc::get_valid_token,
+};
+use anyhow::anyhow;
+use std::{path::PathBuf, time::Duration};
+use time::OffsetDateTime;
+use tokio::sync::{Mutex, RwLock};
+use url::Url;
+
+/// Minimum remaining validity of a token, unless configured otherwise
+const DEFAULT_MIN_VALID: Duration = Duration::from_secs(30);
+
+/// Delay before retrying a failed refresh in the background, doubled for each failed attempt
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Maximum delay before retrying a failed refresh in the background
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// Minimum delay between refreshing tokens in the background
+const MIN_DELAY: Duration = Duration::from_secs(5);
+
+/// Provides the current token of a client, refreshing it when necessary
+pub struct TokenSource {
+    config: Option<PathBuf>,
+    name: String,
+    http: HttpOptions,
+    min_valid: Duration,
+    state: RwLock<ClientState>,
+    dpop: Option<DpopKey>,
+    refresh: Mutex<()>,
+}
+
+impl TokenSource {
+    /// Create a new source, using a token which is valid for at least `min_valid`
+    pub async fn new(
+        config: Option<PathBuf>,
+        name: String,
+        http: HttpOptions,
+        min_valid: Option<Duration>,
+    ) -> anyhow::Result<Self> {
+        let (state, dpop, min_valid, http) = Config::locked(config.as_deref(), async |config| {
+            let client = config
+                .by_name_mut(&name)
+                .ok_or_else(|| anyhow!("unknown client '{name}'"))?;
+
+            let min_valid = min_valid.or(client.min_valid).unwrap_or(DEFAULT_MIN_VALID);
+            let state = get_valid_token(client, &http, Some(min_valid), false).await?;
+
+            // certificate bound tokens require the certificate of the client for all requests
+            let http = http.clone().with_certificate(client.r#type.certificate());
+
+            Ok((state, client.dpop.clone(), min_valid, http))
+        })
+        .await?;
+
+        Ok(Self {
+            config,
+            name,
+            http,
+            min_valid,
+            state: RwLock::new(state),
+            dpop,
+            refresh: Mutex::new(()),
+        })
+    }
+
+    /// The HTTP options for requests using the token, including the certificate of the client
+    pub fn http(&self) -> &HttpOptions {
+        &self.http
+    }
+
+    /// The minimum remaining validity of the token, before it gets refreshed
+    pub fn min_valid(&self) -> Duration {
+        self.min_valid
+    }
+
+    /// The DPoP key of the client, if it uses DPoP
+    pub fn dpop(&self) -> Option<&DpopKey> {
+        self.dpop.as_ref()
+    }
+
+    /// The current state, containing all tokens
+    pub async fn state(&self) -> ClientState {
+        self.state.read().await.clone()
+    }
+
+    /// The current access token
+    pub async fn token(&self) -> String {
+        self.state.read().await.access_token.clone()
+    }
+
+    /// The `Authorization` header (and a DPoP proof) for a request
+    pub fn authorization(
+        &self,
+        token: &str,
+        method: &str,
+        url: &Url,
+    ) -> anyhow::Result<(String, Option<String>)> {
+        Ok(match &self.dpop {
+            Some(dpop) => (
+                format!("DPoP {token}"),
+                Some(dpop.proof(method, url, None, Some(token))?),
+            ),
+            None => (format!("Bearer {token}"), None),
+        })
+    }
+
+    /// Refresh the token, if it isn't valid for the minimum duration anymore
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        let _guard = self.refresh.lock().await;
+
+        let state = Config::locked(self.config.as_deref(), async |config| {
+            let client = self.client(config)?;
+            get_valid_token(client, &self.http, Some(self.min_valid), false).await
+        })
+        .await?;
+
+        *self.state.write().await = state;
+
+        Ok(())
+    }
+
+    /// Force a new token, unless the rejected token was already replaced in the meantime
+    pub async fn renew(&self, rejected: &str) -> anyhow::Result<()> {
+        let _guard = self.refresh.lock().await;
+        if self.state.read().await.access_token != rejected {
+            return Ok(());
+        }
+
+        log::info!("Token was rejected, fetching a new one");
+
+        let state = Config::locked(self.config.as_deref(), async |config| {
+            let client = self.client(config)?;
+            get_valid_token(client, &self.http, None, true).await
+        })
+        .await?;
+
+        *self.state.write().await = state;
+
+        Ok(())
+    }
+
+    /// Keep the token valid, by refreshing it before it expires
+    pub async fn keep_valid(&self) {
+        let mut retry = RETRY_DELAY;
+
+        loop {
+            let expires = self.state.read().await.expiration();
+            let Some(expires) = expires else {
+                // without an expiration, the token will only be renewed when it is rejected
+                log::debug!("Token has no expiration, not refreshing it in the background");
+                return;
+            };
+
+            let remaining = expires - OffsetDateTime::now_utc();
+            let delay = time::Duration::try_from(self.min_valid)
+                .ok()
+                .and_then(|min_valid| remaining.checked_sub(min_valid));
+            let delay = match delay {
+                Some(delay) if delay.is_positive() => delay,
+                // tokens issued with a shorter lifetime get refreshed halfway to their expiration
+                _ => remaining / 2,
+            };
+            // never refresh in a tight loop, even if the issuer only provides short-lived tokens
+            let delay = Duration::try_from(delay)
+                .unwrap_or(Duration::ZERO)
+                .max(MIN_DELAY);
+
+            log::debug!("Refreshing token in {}s", delay.as_secs());
+            tokio::time::sleep(delay).await;
+
+            match self.refresh().await {
+                Ok(()) => retry = RETRY_DELAY,
+                Err(err) => {
+                    log::warn!(
+                        "Failed to refresh token of '{}', retrying in {}: {err}",
+                        self.name,
+                        humantime::format_duration(retry)
+                    );
+                    tokio::time::sleep(retry).await;
+                    retry = (retry * 2).min(MAX_RETRY_DELAY);
+                }
+            }
+        }
+    }
+
+    fn client<'c>(&self, config: &'c mut Config) -> anyhow::Result<&'c mut Client> {
+        config
+            .by_name_mut(&self.name)
+            .ok_or_else(|| anyhow!("unknown client '{}'", self.name))
+    }
+}
@@ -1,15 +1,22 @@
+use crate::utils::truncated;
+use anyhow::anyhow;
 use biscuit::{Base64Url, CompactPart};
 use colored_json::to_colored_json_auto;
+use comfy_table::{Cell, Color, ContentArrangement, Table, presets};
 use pretty_hex::pretty_hex;
 use serde_json::Value;
 use std::io::{Write, stdout};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+/// Claims containing a NumericDate
+const TIMESTAMP_CLAIMS: [&str; 4] = ["exp", "iat", "nbf", "auth_time"];
 
 pub fn inspect(token: String) -> anyhow::Result<()> {
     let token = biscuit::Compact::decode(&token);
 
     for (n, part) in token.parts.into_iter().enumerate() {
         print!(" Part #{n}:");
-        if let Err(err) = inspect_part(part) {
+        if let Err(err) = inspect_part(n, part) {
             println!("Unable to decode: {err}");
         }
     }
@@ -17,7 +24,8 @@ pub fn inspect(token: String) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub fn inspect_part(part: Base64Url) -> anyhow::Result<()> {
+/// Inspect a part of a token, annotating the header (first part) and the claims (second part)
+pub fn inspect_part(n: usize, part: Base64Url) -> anyhow::Result<()> {
     let data = part.to_bytes()?;
     match serde_json::from_slice::<Value>(&data) {
         Err(err) => {
@@ -31,12 +39,99 @@ pub fn inspect_part(part: Base64Url) -> anyhow::Result<()> {
         }
         Ok(value) => {
             println!();
+            if n == 0 {
+                println!("{}", header_table(&value));
+            }
             println!("{}", to_colored_json_auto(&value)?);
+            if n == 1
+                && let Some(table) = timestamp_table(&value)?
+            {
+                println!("{table}");
+            }
         }
     }
     Ok(())
 }
 
+/// Summarize the most relevant header fields
+fn header_table(header: &Value) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(presets::ASCII_MARKDOWN)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(["alg", "kid", "typ"]);
+
+    table.add_row(
+        ["alg", "kid", "typ"].map(|field| header[field].as_str().unwrap_or("").to_string()),
+    );
+
+    table
+}
+
+/// Render the timestamp claims in a human-readable way, highlighting expired tokens
+fn timestamp_table(claims: &Value) -> anyhow::Result<Option<Table>> {
+    let now = OffsetDateTime::now_utc();
+
+    let mut table = Table::new();
+    table
+        .load_preset(presets::ASCII_MARKDOWN)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(["Claim", "Timestamp", "Relative"]);
+
+    for claim in TIMESTAMP_CLAIMS {
+        // a NumericDate may contain fractions of a second
+        let Some(timestamp) = claims[claim]
+            .as_f64()
+            .and_then(|value| OffsetDateTime::from_unix_timestamp(value as i64).ok())
+        else {
+            continue;
+        };
+
+        let rem = timestamp - now;
+        let past = truncated(rem);
+
+        let relative = match (claim, rem.is_positive()) {
+            ("exp", true) => Cell::new(format!("expires in {past}")).fg(Color::Green),
+            ("exp", false) => Cell::new(format!("expired {past} ago")).fg(Color::Red),
+            ("nbf", true) => Cell::new(format!("valid in {past}")).fg(Color::Red),
+            ("nbf", false) => Cell::new(format!("valid since {past}")),
+            ("auth_time", _) => Cell::new(format!("authenticated {past} ago")),
+            (_, true) => Cell::new(format!("issued in {past}")).fg(Color::Red),
+            (_, false) => Cell::new(format!("issued {past} ago")),
+        };
+
+        table.add_row(vec![
+            Cell::new(claim),
+            Cell::new(timestamp.format(&Rfc3339)?),
+            relative,
+        ]);
+    }
+
+    Ok((!table.is_empty()).then_some(table))
+}
+
+/// Show the certificate binding (RFC 8705) of a token, compared to a certificate thumbprint
+pub fn inspect_binding(token: &str, thumbprint: &str) -> anyhow::Result<()> {
+    let token = biscuit::Compact::decode(token);
+    let payload = token
+        .parts
+        .get(1)
+        .ok_or_else(|| anyhow!("token has no payload"))?;
+    let payload: Value = serde_json::from_slice(&payload.to_bytes()?)?;
+
+    match payload["cnf"]["x5t#S256"].as_str() {
+        None => println!("Certificate binding: token is not bound to a certificate"),
+        Some(bound) if bound == thumbprint => {
+            println!("Certificate binding: {bound} (matches the client certificate)")
+        }
+        Some(bound) => println!(
+            "Certificate binding: {bound} (does NOT match the client certificate: {thumbprint})"
+        ),
+    }
+
+    Ok(())
+}
+
 fn is_text(data: &[u8]) -> bool {
     String::from_utf8(data.to_vec()).is_ok()
 }
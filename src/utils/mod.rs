@@ -1,4 +1,5 @@
 pub mod inspect;
+pub mod verify;
 
 use std::fmt::{Display, Formatter};
 
@@ -18,3 +19,8 @@ where
         }
     }
 }
+
+/// Convert into a human-readable duration, truncated to seconds, ignoring the sign
+pub fn truncated(duration: time::Duration) -> humantime::Duration {
+    std::time::Duration::from_secs(duration.unsigned_abs().as_secs()).into()
+}
@@ -0,0 +1,152 @@
+use crate::utils::truncated;
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use time::{Duration, OffsetDateTime};
+
+/// The outcome of a single check
+pub enum Outcome {
+    Passed(String),
+    Failed(String),
+    Skipped(String),
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Passed(details) => write!(f, "ok ({details})"),
+            Self::Failed(details) => write!(f, "FAILED ({details})"),
+            Self::Skipped(details) => write!(f, "skipped ({details})"),
+        }
+    }
+}
+
+/// A check performed on a token
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Outcome,
+}
+
+impl Check {
+    pub fn new(name: &'static str, outcome: Outcome) -> Self {
+        Self { name, outcome }
+    }
+}
+
+/// What the claims of a token are expected to be
+pub struct Expectations {
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    pub azp: Option<String>,
+    pub leeway: Duration,
+}
+
+/// Check the registered claims of a token
+pub fn check_claims(claims: &Value, expected: &Expectations) -> Vec<Check> {
+    let now = OffsetDateTime::now_utc();
+    let leeway = expected.leeway;
+
+    let mut checks = vec![];
+
+    checks.push(Check::new(
+        "exp",
+        match numeric_date(claims, "exp") {
+            None => Outcome::Skipped("no expiration".into()),
+            // beyond the representable range, the token can't be expired yet
+            Some(exp) if exp.checked_add(leeway).is_some_and(|exp| exp < now) => {
+                Outcome::Failed(format!("expired {} ago", truncated(now - exp)))
+            }
+            Some(exp) if exp < now => Outcome::Passed(format!(
+                "expired {} ago, within leeway",
+                truncated(now - exp)
+            )),
+            Some(exp) => Outcome::Passed(format!("expires in {}", truncated(exp - now))),
+        },
+    ));
+
+    checks.push(Check::new(
+        "nbf",
+        match numeric_date(claims, "nbf") {
+            None => Outcome::Skipped("not present".into()),
+            Some(nbf) if nbf.checked_sub(leeway).is_some_and(|nbf| nbf > now) => {
+                Outcome::Failed(format!("not valid before {}", truncated(nbf - now)))
+            }
+            Some(_) => Outcome::Passed("already valid".into()),
+        },
+    ));
+
+    checks.push(Check::new(
+        "iat",
+        match numeric_date(claims, "iat") {
+            None => Outcome::Skipped("not present".into()),
+            Some(iat) if iat.checked_sub(leeway).is_some_and(|iat| iat > now) => {
+                Outcome::Failed(format!("issued {} in the future", truncated(iat - now)))
+            }
+            Some(iat) => Outcome::Passed(format!("issued {} ago", truncated(now - iat))),
+        },
+    ));
+
+    let iss = claims["iss"].as_str();
+    checks.push(Check::new(
+        "iss",
+        match (iss, &expected.issuer) {
+            (None, _) => Outcome::Failed("no issuer".into()),
+            (Some(iss), Some(expected)) if iss != expected => {
+                Outcome::Failed(format!("{iss}, expected: {expected}"))
+            }
+            (Some(iss), _) => Outcome::Passed(iss.to_string()),
+        },
+    ));
+
+    if let Some(expected) = &expected.audience {
+        let aud = match &claims["aud"] {
+            Value::String(aud) => vec![aud.as_str()],
+            Value::Array(aud) => aud.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        checks.push(Check::new(
+            "aud",
+            match aud.contains(&expected.as_str()) {
+                true => Outcome::Passed(expected.clone()),
+                false => Outcome::Failed(format!("{}, expected: {expected}", aud.join(", "))),
+            },
+        ));
+    }
+
+    if let Some(expected) = &expected.azp {
+        checks.push(Check::new(
+            "azp",
+            match claims["azp"].as_str() {
+                Some(azp) if azp == expected => Outcome::Passed(azp.to_string()),
+                azp => Outcome::Failed(format!("{}, expected: {expected}", azp.unwrap_or("n/a"))),
+            },
+        ));
+    }
+
+    checks
+}
+
+/// Print the results of the checks, returning `true` if the token is valid
+pub fn report(checks: &[Check]) -> bool {
+    println!();
+    println!(" Verification:");
+    for check in checks {
+        println!("  {:>9}: {}", check.name, check.outcome);
+    }
+
+    let valid = !checks
+        .iter()
+        .any(|check| matches!(check.outcome, Outcome::Failed(_)));
+
+    match valid {
+        true => println!(" Verdict: VALID"),
+        false => println!(" Verdict: INVALID"),
+    }
+
+    valid
+}
+
+fn numeric_date(claims: &Value, name: &str) -> Option<OffsetDateTime> {
+    // a NumericDate may contain fractions of a second
+    let value = claims[name].as_f64()?;
+    OffsetDateTime::from_unix_timestamp(value as i64).ok()
+}